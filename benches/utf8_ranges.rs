@@ -0,0 +1,27 @@
+//! Measures conversion of large `Utf8Charset` ranges into UTnfa.
+//! Run with `cargo bench --bench utf8_ranges`
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rcclex::{Automata, UTnfa, Utf8Charset};
+
+const ITERATIONS: u32 = 200;
+
+fn main() {
+    for (name, range) in [
+        ("ascii", ('\0', '\u{7f}')),
+        ("bmp", ('\0', '\u{ffff}')),
+        ("all", ('\0', char::MAX)),
+        ("unaligned", ('\u{3a5}', '\u{10abc}')),
+    ] {
+        let mut nodes = 0;
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let a: UTnfa = black_box(Utf8Charset::from_iter([range])).into();
+            nodes = black_box(a).nodes();
+        }
+        let elapsed = start.elapsed() / ITERATIONS;
+        println!("{name:<10} {elapsed:>12.2?}/iter {nodes:>4} nodes");
+    }
+}
@@ -1,5 +1,12 @@
+use crate::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use crate::prelude::*;
 use itertools::Itertools;
-use std::collections::HashSet;
+
+use core::fmt::Display;
+
+use crate::{Charset, DisplayStyle};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Common trait for all UTnfa, Tnfa, Tdfa
 pub trait Automata {
@@ -15,6 +22,527 @@ pub trait Automata {
     /// Returns list of all non-epsilon edges
     fn list_edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>, isize)>;
 
+    /// Returns list of all edges, where chars of edges with the same endpoints and tag
+    /// are grouped into a single charset, and epsilon edges have `None` as charset.
+    /// Unlike `list_edges`, a charset of many chars gives a single edge
+    fn list_range_edges(&self) -> impl Iterator<Item = (usize, usize, Option<Charset>, isize)> {
+        let mut edges: BTreeMap<(usize, usize, isize), Charset> = BTreeMap::new();
+        let mut eps_edges = Vec::new();
+        for (a, b, c, t) in self.list_edges() {
+            match c {
+                None => eps_edges.push((a, b, None, t)),
+                Some(c) => *edges.entry((a, b, t)).or_default() |= Charset::from_char(c),
+            }
+        }
+        edges
+            .into_iter()
+            .map(|((a, b, t), c)| (a, b, Some(c), t))
+            .chain(eps_edges)
+    }
+
+    /// Returns all final states in increasing order
+    fn final_states(&self) -> Vec<usize> {
+        Vec::from_iter((0..self.nodes()).filter(|n| self.is_final(*n)))
+    }
+
+    /// Returns number of final states
+    fn num_final_states(&self) -> usize {
+        (0..self.nodes()).filter(|n| self.is_final(*n)).count()
+    }
+
+    /// Returns `true` if `self` has no epsilon edges,
+    /// and no two edges, leaving the same state, share a char
+    fn is_deterministic(&self) -> bool {
+        let mut out: HashMap<usize, HashSet<u8>> = HashMap::new();
+        for (a, _, c, _) in self.list_edges() {
+            match c {
+                None => return false,
+                Some(c) if !out.entry(a).or_default().insert(c) => return false,
+                _ => continue,
+            }
+        }
+        true
+    }
+
+    /// Returns the largest number of edges (including epsilon ones), leaving a single state.
+    /// Edges are counted per char, as listed by `list_edges`
+    fn max_out_degree(&self) -> usize {
+        let mut degree = vec![0; self.nodes()];
+        for (a, _, _, _) in self.list_edges() {
+            degree[a] += 1;
+        }
+        degree.into_iter().max().unwrap_or(0)
+    }
+
+    /// Returns the number of edges (including epsilon ones), as listed by `list_edges`
+    fn total_edges(&self) -> usize {
+        self.list_edges().count()
+    }
+
+    /// Returns set of all chars, that label some non-epsilon edge
+    fn alphabet(&self) -> Charset {
+        self.list_range_edges()
+            .filter_map(|e| e.2)
+            .fold(Charset::empty(), |a, c| a | c)
+    }
+
+    /// Returns all edges `(char, to, tag)`, that leave `state`, epsilon edges have `None` as char
+    fn transitions_from(&self, state: usize) -> Vec<(Option<u8>, usize, isize)> {
+        Vec::from_iter(
+            self.list_edges()
+                .filter(|e| e.0 == state)
+                .map(|(_, b, c, t)| (c, b, t)),
+        )
+    }
+
+    /// Returns info of all states in order of their indices, each with its edges,
+    /// in the same format as `transitions_from`, but `list_edges` is only traversed once
+    fn states(&self) -> impl Iterator<Item = StateInfo> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, c, t) in self.list_edges() {
+            edges[a].push((c, b, t));
+        }
+        edges
+            .into_iter()
+            .enumerate()
+            .map(|(id, out_edges)| StateInfo {
+                id,
+                is_final: self.is_final(id),
+                out_edges,
+            })
+    }
+
+    /// Returns sorted list of states, reachable from `state` by a single edge with char `c`.
+    /// Epsilon edges are not followed, so deterministic automata return at most one state
+    fn step(&self, state: usize, c: u8) -> Vec<usize> {
+        Vec::from_iter(
+            self.list_edges()
+                .filter(|e| e.0 == state && e.2 == Some(c))
+                .map(|e| e.1)
+                .sorted()
+                .dedup(),
+        )
+    }
+
+    /// Returns transition table of deterministic `self` and its finals, i.e. `(delta, finals)`,
+    /// where `delta[n][c]` is the state, reached from `n` by char `c`, if there is one,
+    /// and `finals[n]` is `true` if `n` is final. Tags are ignored.
+    /// Panics if `self` is not deterministic
+    fn transition_table(&self) -> (Vec<[Option<usize>; 256]>, Vec<bool>) {
+        assert!(
+            self.is_deterministic(),
+            "transition_table() requires deterministic automata"
+        );
+        let mut delta = vec![[None; 256]; self.nodes()];
+        for (a, b, c, _) in self.list_edges() {
+            let Some(c) = c else {
+                panic!("transition_table() requires deterministic automata");
+            };
+            delta[a][c as usize] = Some(b);
+        }
+        let finals = Vec::from_iter((0..self.nodes()).map(|n| self.is_final(n)));
+        (delta, finals)
+    }
+
+    /// Returns `true` if `self` accepts `input`, tags are ignored
+    fn accepts(&self, input: &[u8]) -> bool {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, c, _) in self.list_edges() {
+            edges[a].push((b, c));
+        }
+        let mut states = vec![false; self.nodes()];
+        states[self.begin()] = true;
+        eps_closure(&edges, &mut states);
+        for c in input.iter() {
+            let mut next = vec![false; self.nodes()];
+            for (a, _) in states.iter().enumerate().filter(|(_, s)| **s) {
+                for (b, _) in edges[a].iter().filter(|(_, e)| *e == Some(*c)) {
+                    next[*b] = true;
+                }
+            }
+            eps_closure(&edges, &mut next);
+            states = next;
+        }
+        states
+            .iter()
+            .enumerate()
+            .any(|(n, s)| *s && self.is_final(n))
+    }
+
+    /// Returns trace of deterministic `self` on accepted `input`, or `None` if `input` is rejected.
+    /// The trace starts with `(begin, None, -1)`, followed by `(state, Some(c), tag)` for each
+    /// char `c` of `input`, where `state` is reached by the edge with char `c` and tag `tag`,
+    /// as it is returned by `list_edges`, e.g. for Tdfa it's a tag operation.
+    /// Panics if `self` is not deterministic
+    fn trace(&self, input: &[u8]) -> Option<Vec<(usize, Option<u8>, isize)>> {
+        assert!(
+            self.is_deterministic(),
+            "trace() requires deterministic automata"
+        );
+        let mut delta = vec![[None; 256]; self.nodes()];
+        for (a, b, c, t) in self.list_edges() {
+            let Some(c) = c else {
+                panic!("trace() requires deterministic automata");
+            };
+            delta[a][c as usize] = Some((b, t));
+        }
+        let mut res = vec![(self.begin(), None, -1)];
+        let mut state = self.begin();
+        for c in input.iter() {
+            let (next, t) = delta[state][*c as usize]?;
+            res.push((next, Some(*c), t));
+            state = next;
+        }
+        self.is_final(state).then_some(res)
+    }
+
+    /// Returns `true` if no final state is reachable from the initial one
+    fn is_empty_language(&self) -> bool {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, _, _) in self.list_edges() {
+            edges[a].push(b);
+        }
+        let mut visited = vec![false; self.nodes()];
+        let mut stack = vec![self.begin()];
+        visited[self.begin()] = true;
+        while let Some(n) = stack.pop() {
+            if self.is_final(n) {
+                return false;
+            }
+            for m in edges[n].iter() {
+                if !visited[*m] {
+                    visited[*m] = true;
+                    stack.push(*m);
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns all states, reachable from `start` by edges (including epsilon ones),
+    /// `start` itself included
+    fn reachable_from(&self, start: usize) -> BTreeSet<usize> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, _, _) in self.list_edges() {
+            edges[a].push(b);
+        }
+        reach(&edges, start)
+    }
+
+    /// Returns all states, from which `target` is reachable by edges (including epsilon ones),
+    /// `target` itself included
+    fn coreachable_to(&self, target: usize) -> BTreeSet<usize> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, _, _) in self.list_edges() {
+            edges[b].push(a);
+        }
+        reach(&edges, target)
+    }
+
+    /// Returns `true` if there is a cycle of edges (including epsilon ones), reachable from
+    /// the initial state. Automata without cycles accept only a finite language
+    fn has_cycle(&self) -> bool {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, _, _) in self.list_edges() {
+            edges[a].push(b);
+        }
+        // 0 - not visited, 1 - on the current path, 2 - done
+        let mut color = vec![0; self.nodes()];
+        let mut stack = vec![(self.begin(), 0)];
+        color[self.begin()] = 1;
+        while let Some((n, i)) = stack.pop() {
+            let Some(m) = edges[n].get(i).copied() else {
+                color[n] = 2;
+                continue;
+            };
+            stack.push((n, i + 1));
+            match color[m] {
+                0 => {
+                    color[m] = 1;
+                    stack.push((m, 0));
+                }
+                1 => return true,
+                _ => continue,
+            }
+        }
+        false
+    }
+
+    /// Returns strongly connected components of the graph of all edges (including epsilon ones),
+    /// found by Tarjan's algorithm. Components are listed in reverse topological order,
+    /// states of each component are sorted
+    fn sccs(&self) -> Vec<Vec<usize>> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, _, _) in self.list_edges() {
+            edges[a].push(b);
+        }
+        let mut index = vec![usize::MAX; self.nodes()];
+        let mut low = vec![0; self.nodes()];
+        let mut on_stack = vec![false; self.nodes()];
+        let mut stack = Vec::new();
+        let mut res = Vec::new();
+        let mut counter = 0;
+        for root in 0..self.nodes() {
+            if index[root] != usize::MAX {
+                continue;
+            }
+            let mut calls = vec![(root, 0)];
+            (index[root], low[root], on_stack[root]) = (counter, counter, true);
+            stack.push(root);
+            counter += 1;
+            while let Some((n, i)) = calls.last_mut() {
+                let n = *n;
+                if let Some(m) = edges[n].get(*i).copied() {
+                    *i += 1;
+                    if index[m] == usize::MAX {
+                        (index[m], low[m], on_stack[m]) = (counter, counter, true);
+                        stack.push(m);
+                        counter += 1;
+                        calls.push((m, 0));
+                    } else if on_stack[m] {
+                        low[n] = low[n].min(index[m]);
+                    }
+                    continue;
+                }
+                calls.pop();
+                if let Some((p, _)) = calls.last() {
+                    low[*p] = low[*p].min(low[n]);
+                }
+                if low[n] == index[n] {
+                    let mut scc = Vec::new();
+                    while let Some(m) = stack.pop() {
+                        on_stack[m] = false;
+                        scc.push(m);
+                        if m == n {
+                            break;
+                        }
+                    }
+                    scc.sort();
+                    res.push(scc);
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the shortest string accepted by `self`, or `None` if the language is empty.
+    /// If there are many such strings, the lexicographically smallest one is returned
+    fn shortest_match(&self) -> Option<Vec<u8>> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, c, _) in self.list_edges() {
+            edges[a].push((b, c));
+        }
+        // `level` contains the smallest string of the current length for each state reached by it
+        let mut level: BTreeMap<usize, Vec<u8>> = BTreeMap::from([(self.begin(), Vec::new())]);
+        // states, reached by shorter strings
+        let mut visited = vec![false; self.nodes()];
+        while !level.is_empty() {
+            // states are visited in order of their strings, and a state, reached by a smaller string,
+            // is updated, even if it was reached by a char edge, so each one gets the smallest string
+            let sources = Vec::from_iter(level.iter().map(|(n, s)| (s.clone(), *n)).sorted());
+            for (_, n) in sources {
+                let mut stack = vec![n];
+                while let Some(n) = stack.pop() {
+                    let s = level[&n].clone();
+                    for (m, _) in edges[n].iter().filter(|(_, c)| c.is_none()) {
+                        if !visited[*m] && level.get(m).is_none_or(|u| s < *u) {
+                            level.insert(*m, s.clone());
+                            stack.push(*m);
+                        }
+                    }
+                }
+            }
+            for n in level.keys() {
+                visited[*n] = true;
+            }
+            if let Some(s) = level
+                .iter()
+                .filter(|(n, _)| self.is_final(**n))
+                .map(|(_, s)| s)
+                .min()
+            {
+                return Some(s.clone());
+            }
+            let mut next: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+            for (n, s) in level.iter() {
+                for (m, c) in edges[*n].iter().filter(|(m, _)| !visited[*m]) {
+                    let Some(c) = c else {
+                        continue;
+                    };
+                    let t = Vec::from_iter(s.iter().copied().chain([*c]));
+                    if next.get(m).is_none_or(|u| t < *u) {
+                        next.insert(*m, t);
+                    }
+                }
+            }
+            level = next;
+        }
+        None
+    }
+
+    /// Returns all strings of length at most `n`, accepted by `self`, in lexicographical order.
+    /// The number of such strings grows exponentially with `n`, so it's intended for small `n` only
+    fn matches_up_to(&self, n: usize) -> Vec<Vec<u8>> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, c, _) in self.list_edges() {
+            edges[a].push((b, c));
+        }
+        let mut states = vec![false; self.nodes()];
+        states[self.begin()] = true;
+        eps_closure(&edges, &mut states);
+
+        let mut res = Vec::new();
+        let mut stack = vec![(Vec::new(), states)];
+        while let Some((s, states)) = stack.pop() {
+            if (0..self.nodes()).any(|n| states[n] && self.is_final(n)) {
+                res.push(s.clone());
+            }
+            if s.len() == n {
+                continue;
+            }
+            let mut next: BTreeMap<u8, Vec<bool>> = BTreeMap::new();
+            for (a, _) in states.iter().enumerate().filter(|(_, s)| **s) {
+                for (b, c) in edges[a].iter() {
+                    if let Some(c) = c {
+                        next.entry(*c).or_insert_with(|| vec![false; self.nodes()])[*b] = true;
+                    }
+                }
+            }
+            // pushed in reverse order, so that strings are popped in lexicographical order
+            for (c, mut states) in next.into_iter().rev() {
+                eps_closure(&edges, &mut states);
+                stack.push((Vec::from_iter(s.iter().copied().chain([c])), states));
+            }
+        }
+        res
+    }
+
+    /// Searches for a match of `self` anywhere in `input`, returning its span `(start, end)`.
+    /// Matching follows leftmost-longest semantics: the match with the smallest `start` is taken,
+    /// and among them the one with the largest `end`. Empty matches are allowed,
+    /// if `self` accepts the empty string. Tags are ignored, so anchors (see `Anchor`) are treated
+    /// as if they always hold; use `Matcher::find` to evaluate them
+    fn find(&self, input: &[u8]) -> Option<(usize, usize)> {
+        self.find_iter(input).next()
+    }
+
+    /// Returns iterator over all non-overlapping matches of `self` in `input`, in order of their starts.
+    /// Each match is found by `find` in the rest of input after the previous match.
+    /// After an empty match, the search is resumed from the next byte
+    fn find_iter(&self, input: &[u8]) -> impl Iterator<Item = (usize, usize)> {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, c, _) in self.list_edges() {
+            edges[a].push((b, c));
+        }
+        let finals = Vec::from_iter((0..self.nodes()).map(|n| self.is_final(n)));
+        let begin = self.begin();
+        let mut pos = 0;
+        core::iter::from_fn(move || {
+            while pos <= input.len() {
+                let start = pos;
+                pos += 1;
+                if let Some(end) = longest_match(&edges, &finals, begin, &input[start..]) {
+                    pos = core::cmp::max(pos, start + end);
+                    return Some((start, start + end));
+                }
+            }
+            None
+        })
+    }
+
+    /// Renders `self` as a text table, suitable for snapshot tests: a line with the initial state,
+    /// followed by each state with its outgoing edges, one per line.
+    /// Chars of edges are grouped into charsets, e.g. `[abc]`, epsilon edges are shown as `ε`,
+    /// and tags are appended as `/t=N`. Edges are sorted, so the output is deterministic
+    fn to_debug_table(&self) -> String {
+        let mut edges = vec![Vec::new(); self.nodes()];
+        for (a, b, c, t) in self.list_range_edges() {
+            edges[a].push((c.is_none(), b, t, c));
+        }
+        let mut s = format!("begin: {}\n", self.begin());
+        for (n, e) in edges.iter_mut().enumerate() {
+            e.sort_by_key(|(eps, b, t, c)| (*eps, *b, *t, c.and_then(|c| c.first())));
+            let final_ = if self.is_final(n) { " (final)" } else { "" };
+            s += &format!("{n}{final_}:\n");
+            for (_, b, t, c) in e.iter() {
+                let label = match c {
+                    Some(c) => format!("[{c}]"),
+                    None => "ε".to_string(),
+                };
+                match t {
+                    -1 => s += &format!("  {label} -> {b}\n"),
+                    _ => s += &format!("  {label}/t={t} -> {b}\n"),
+                }
+            }
+        }
+        s
+    }
+
+    /// Reconstructs regex, that matches the same language as `self`, by state elimination.
+    /// The regex uses syntax of `parse_bytes`: chars of edges are grouped into classes,
+    /// and non-printable bytes are written as `\xNN`. Tags are ignored, so anchors are treated
+    /// as epsilon edges. If `self` accepts nothing, an empty class `[^\x00-\xff]` is returned
+    fn to_regex(&self) -> String {
+        // node `n` is the initial state, node `n + 1` is the only final one
+        let n = self.nodes();
+        let mut labels: Vec<Vec<Option<Regex>>> = vec![vec![None; n + 2]; n + 2];
+        let mut add = |a: usize, b: usize, r: Regex| {
+            labels[a][b] = Some(match labels[a][b].take() {
+                Some(l) => l.alt(r),
+                None => r,
+            });
+        };
+        add(n, self.begin(), Regex::empty());
+        for f in self.final_states() {
+            add(f, n + 1, Regex::empty());
+        }
+        for (a, b, c, _) in self.list_range_edges() {
+            add(a, b, c.map_or_else(Regex::empty, Regex::Class));
+        }
+        for q in 0..n {
+            let l = labels[q][q].take().map_or_else(Regex::empty, Regex::star);
+            for a in (0..n + 2).filter(|a| *a != q) {
+                let Some(into) = labels[a][q].take() else {
+                    continue;
+                };
+                for b in (0..n + 2).filter(|b| *b != q) {
+                    if let Some(out) = &labels[q][b] {
+                        let r = into.clone().concat(l.clone()).concat(out.clone());
+                        labels[a][b] = Some(match labels[a][b].take() {
+                            Some(ab) => ab.alt(r),
+                            None => r,
+                        });
+                    }
+                }
+            }
+            labels[q].iter_mut().for_each(|l| *l = None);
+        }
+        match labels[n][n + 1].take() {
+            Some(r) => r.to_string(),
+            None => Regex::Class(Charset::empty()).to_string(),
+        }
+    }
+
+    /// Copies `self` into SimpleAutomata with the same states, finals and edges, including tags
+    fn to_simple(&self) -> SimpleAutomata {
+        SimpleAutomata {
+            begin: self.begin(),
+            nodes: self.nodes(),
+            finals: HashSet::from_iter(self.final_states()),
+            edges: Vec::from_iter(self.list_edges()),
+        }
+    }
+
+    /// Same as `to_simple`, but tags of all edges are dropped, i.e. replaced by `-1`
+    fn to_untagged(&self) -> SimpleAutomata {
+        SimpleAutomata {
+            begin: self.begin(),
+            nodes: self.nodes(),
+            finals: HashSet::from_iter(self.final_states()),
+            edges: Vec::from_iter(self.list_edges().map(|(a, b, c, _)| (a, b, c, -1))),
+        }
+    }
+
     /// Returns `true` if `self` represents the same automata as `other`
     fn eq<T>(&self, other: &T) -> bool
     where
@@ -23,8 +551,8 @@ pub trait Automata {
         if self.nodes() != other.nodes() {
             return false;
         }
-        let self_edges: HashSet<_> = HashSet::from_iter(self.list_edges());
-        let other_edges: HashSet<_> = HashSet::from_iter(other.list_edges());
+        let self_edges = group_edges(self);
+        let other_edges = group_edges(other);
         if self_edges.len() != other_edges.len() {
             return false;
         }
@@ -32,24 +560,262 @@ pub trait Automata {
             if v[self.begin()] != other.begin() {
                 continue;
             }
-            for (i, n) in v.iter().enumerate() {
-                if self.is_final(i) != other.is_final(*n) {
-                    continue;
-                }
+            if v.iter()
+                .enumerate()
+                .any(|(i, n)| self.is_final(i) != other.is_final(*n))
+            {
+                continue;
             }
             if self_edges
                 .iter()
-                .all(|(a, b, c, t)| other_edges.contains(&(v[*a], v[*b], *c, *t)))
+                .all(|((a, b, t, e), c)| other_edges.get(&(v[*a], v[*b], *t, *e)) == Some(c))
             {
                 return true;
             }
         }
-        return false;
+        false
+    }
+}
+
+/// State of automata, returned by `Automata::states`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateInfo {
+    /// Index of the state
+    pub id: usize,
+    /// `true` if the state is final
+    pub is_final: bool,
+    /// All edges `(char, to, tag)`, that leave the state, epsilon edges have `None` as char
+    pub out_edges: Vec<(Option<u8>, usize, isize)>,
+}
+
+/// Returns all nodes, reachable from `start` in graph with adjacency lists `edges`
+fn reach(edges: &[Vec<usize>], start: usize) -> BTreeSet<usize> {
+    let mut res = BTreeSet::from([start]);
+    let mut stack = vec![start];
+    while let Some(n) = stack.pop() {
+        for m in edges[n].iter() {
+            if res.insert(*m) {
+                stack.push(*m);
+            }
+        }
+    }
+    res
+}
+
+/// Groups edges of `a` by `(from, to, tag, is_epsilon)`, uniting their charsets.
+/// Charset of epsilon edges is empty
+fn group_edges<A: Automata + ?Sized>(a: &A) -> HashMap<(usize, usize, isize, bool), Charset> {
+    let mut res: HashMap<_, Charset> = HashMap::new();
+    for (a, b, c, t) in a.list_range_edges() {
+        *res.entry((a, b, t, c.is_none())).or_default() |= c.unwrap_or_default();
+    }
+    res
+}
+
+/// Extends set of `states` with all states, reachable from them by epsilon edges.
+/// `edges[n]` is a list of all edges leaving `n`
+fn eps_closure(edges: &[Vec<(usize, Option<u8>)>], states: &mut [bool]) {
+    let mut stack = Vec::from_iter((0..states.len()).filter(|n| states[*n]));
+    while let Some(n) = stack.pop() {
+        for (m, _) in edges[n].iter().filter(|(_, c)| c.is_none()) {
+            if !states[*m] {
+                states[*m] = true;
+                stack.push(*m);
+            }
+        }
+    }
+}
+
+/// Returns length of the longest prefix of `input`, accepted by automata with initial state `begin`.
+/// `edges[n]` is a list of all edges leaving `n`, `finals[n]` is `true` if `n` is final
+fn longest_match(
+    edges: &[Vec<(usize, Option<u8>)>],
+    finals: &[bool],
+    begin: usize,
+    input: &[u8],
+) -> Option<usize> {
+    let mut states = vec![false; edges.len()];
+    states[begin] = true;
+    eps_closure(edges, &mut states);
+    let accepts = |states: &[bool]| (0..edges.len()).any(|n| states[n] && finals[n]);
+    let mut last = accepts(&states).then_some(0);
+    for (i, c) in input.iter().enumerate() {
+        let mut next = vec![false; edges.len()];
+        for (a, _) in states.iter().enumerate().filter(|(_, s)| **s) {
+            for (b, _) in edges[a].iter().filter(|(_, e)| *e == Some(*c)) {
+                next[*b] = true;
+            }
+        }
+        if !next.contains(&true) {
+            break;
+        }
+        eps_closure(edges, &mut next);
+        states = next;
+        if accepts(&states) {
+            last = Some(i + 1);
+        }
+    }
+    last
+}
+
+/// Regex, built by `Automata::to_regex`. Empty concatenation matches the empty string
+#[derive(Clone, Debug, PartialEq)]
+enum Regex {
+    Class(Charset),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Star(Box<Regex>),
+}
+
+impl Regex {
+    /// Regex, that matches only the empty string
+    fn empty() -> Regex {
+        Regex::Concat(Vec::new())
+    }
+
+    /// Returns alternation of `self` and `other`, merging classes and dropping duplicates
+    fn alt(self, other: Regex) -> Regex {
+        let mut res = match self {
+            Regex::Alt(v) => v,
+            r => vec![r],
+        };
+        let other = match other {
+            Regex::Alt(v) => v,
+            r => vec![r],
+        };
+        for r in other {
+            let class = res.iter().position(|x| matches!(x, Regex::Class(_)));
+            match (class, r) {
+                (Some(i), Regex::Class(b)) => res[i] = Regex::Class(b | res[i].class()),
+                (_, r) if res.contains(&r) => {}
+                (_, r) => res.push(r),
+            }
+        }
+        match res.len() {
+            1 => res.pop().unwrap(),
+            _ => Regex::Alt(res),
+        }
+    }
+
+    /// Returns charset of `self`, or an empty one, if `self` is not a class
+    fn class(&self) -> Charset {
+        match self {
+            Regex::Class(c) => *c,
+            _ => Charset::empty(),
+        }
+    }
+
+    /// Returns concatenation of `self` and `other`
+    fn concat(self, other: Regex) -> Regex {
+        let mut res = match self {
+            Regex::Concat(v) => v,
+            r => vec![r],
+        };
+        match other {
+            Regex::Concat(v) => res.extend(v),
+            r => res.push(r),
+        }
+        match res.len() {
+            1 => res.pop().unwrap(),
+            _ => Regex::Concat(res),
+        }
+    }
+
+    /// Returns Kleene closure of `self`, empty alternatives of `self` are dropped, as `(a|)* = a*`
+    fn star(self) -> Regex {
+        match self {
+            Regex::Star(_) => self,
+            Regex::Concat(v) if v.is_empty() => Regex::empty(),
+            Regex::Alt(v) => {
+                let mut v = Vec::from_iter(v.into_iter().filter(|r| *r != Regex::empty()));
+                match v.len() {
+                    1 => v.pop().unwrap().star(),
+                    _ => Regex::Star(Box::new(Regex::Alt(v))),
+                }
+            }
+            r => Regex::Star(Box::new(r)),
+        }
+    }
+
+    /// Writes `self`, where `prec` is precedence of the context:
+    /// 0 for alternation, 1 for concatenation, 2 for operand of a quantifier
+    fn write(&self, f: &mut core::fmt::Formatter<'_>, prec: u8) -> core::fmt::Result {
+        match self {
+            Regex::Class(c) => write_class(f, c),
+            Regex::Concat(v) if v.is_empty() => write!(f, "(?:)"),
+            Regex::Concat(v) => {
+                let group = prec > 1 && v.len() > 1;
+                if group {
+                    write!(f, "(?:")?;
+                }
+                for r in v {
+                    r.write(f, 1)?;
+                }
+                if group {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Regex::Alt(v) => {
+                // `(a|)` is written as `a?`
+                let optional = v.contains(&Regex::empty());
+                let v = Vec::from_iter(v.iter().filter(|r| **r != Regex::empty()));
+                let group = v.len() > 1 && (optional || prec > 0);
+                if group {
+                    write!(f, "(?:")?;
+                }
+                for (i, r) in v.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    r.write(
+                        f,
+                        if v.len() > 1 {
+                            1
+                        } else if optional {
+                            2
+                        } else {
+                            prec
+                        },
+                    )?;
+                }
+                if group {
+                    write!(f, ")")?;
+                }
+                if optional {
+                    write!(f, "?")?;
+                }
+                Ok(())
+            }
+            Regex::Star(r) => {
+                r.write(f, 2)?;
+                write!(f, "*")
+            }
+        }
+    }
+}
+
+impl Display for Regex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Writes charset `c` as a literal, `.` or a class, whichever is shorter.
+/// Chars are rendered by `Charset::regex_body`, the same as in `DisplayStyle::Regex`
+fn write_class(f: &mut core::fmt::Formatter<'_>, c: &Charset) -> core::fmt::Result {
+    match c.len() {
+        0 => write!(f, "[^\\x00-\\xff]"),
+        1 => write!(f, "{}", c.regex_body()),
+        256 => write!(f, "."),
+        n if n > 128 => write!(f, "[^{}]", (!*c).regex_body()),
+        _ => write!(f, "{}", c.display_as(DisplayStyle::Regex)),
     }
 }
 
 /// Generic implementation of Automata
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SimpleAutomata {
     pub begin: usize,
     pub nodes: usize,
@@ -75,6 +841,167 @@ impl Automata for SimpleAutomata {
     }
 }
 
+impl SimpleAutomata {
+    /// Builds minimal automata, that accepts the same language as `self`, using Hopcroft's algorithm.
+    /// `self` must be deterministic, missing transitions are treated as leading to a dead state,
+    /// which is not included into the result, as well as unreachable states.
+    ///
+    /// Tags are preserved: each pair `(char, tag)` is treated as a separate letter of the alphabet.
+    /// Thus two states are merged iff for every string both of them either reject it,
+    /// or accept it, crossing the same tags on the way
+    pub fn minimize(&self) -> SimpleAutomata {
+        assert!(
+            self.is_deterministic(),
+            "minimize() requires deterministic automata"
+        );
+
+        // Complete automata by adding a dead state, `delta[n][i]` is a transition by `alphabet[i]`
+        let dead = self.nodes;
+        let alphabet = Vec::from_iter(BTreeSet::from_iter(
+            self.edges.iter().filter_map(|e| Some((e.2?, e.3))),
+        ));
+        let mut delta = vec![vec![dead; alphabet.len()]; self.nodes + 1];
+        for (a, b, c, t) in self.edges.iter() {
+            let c = c.expect("deterministic automata has no epsilon edges");
+            let i = alphabet
+                .binary_search(&(c, *t))
+                .expect("alphabet contains every (char, tag)");
+            delta[*a][i] = *b;
+        }
+        let mut inverse = vec![vec![Vec::new(); self.nodes + 1]; alphabet.len()];
+        for (a, d) in delta.iter().enumerate() {
+            for (i, b) in d.iter().enumerate() {
+                inverse[i][*b].push(a);
+            }
+        }
+
+        // Hopcroft's partition refinement
+        let (finals, rest): (Vec<_>, Vec<_>) = (0..=self.nodes).partition(|n| self.is_final(*n));
+        let mut blocks = Vec::from_iter([finals, rest].into_iter().filter(|b| !b.is_empty()));
+        let mut block = vec![0; self.nodes + 1];
+        for (i, b) in blocks.iter().enumerate() {
+            for n in b.iter() {
+                block[*n] = i;
+            }
+        }
+        let mut work = BTreeSet::from_iter((0..alphabet.len()).map(|c| (0, c)));
+        while let Some((a, c)) = work.pop_first() {
+            let pre = HashSet::<usize>::from_iter(
+                blocks[a]
+                    .iter()
+                    .flat_map(|n| inverse[c][*n].iter().copied()),
+            );
+            let touched = BTreeSet::from_iter(pre.iter().map(|n| block[*n]));
+            for y in touched {
+                let (inside, outside): (Vec<_>, Vec<_>) =
+                    blocks[y].iter().partition(|n| pre.contains(*n));
+                if outside.is_empty() {
+                    continue;
+                }
+                let z = blocks.len();
+                for n in outside.iter() {
+                    block[*n] = z;
+                }
+                let smaller = if inside.len() <= outside.len() { y } else { z };
+                blocks[y] = inside;
+                blocks.push(outside);
+                for c in 0..alphabet.len() {
+                    if work.contains(&(y, c)) {
+                        work.insert((z, c));
+                    } else {
+                        work.insert((smaller, c));
+                    }
+                }
+            }
+        }
+
+        // Collect reachable blocks, except the dead one
+        let mut index: HashMap<_, _> = HashMap::from([(block[self.begin], 0)]);
+        let mut queue = vec![block[self.begin]];
+        let mut res = SimpleAutomata {
+            begin: 0,
+            nodes: 1,
+            finals: HashSet::new(),
+            edges: Vec::new(),
+        };
+        while let Some(b) = queue.pop() {
+            let n = blocks[b][0];
+            if self.is_final(n) {
+                res.finals.insert(index[&b]);
+            }
+            for (i, (c, t)) in alphabet.iter().enumerate() {
+                let to = block[delta[n][i]];
+                if to == block[dead] {
+                    continue;
+                }
+                let next = *index.entry(to).or_insert_with(|| {
+                    queue.push(to);
+                    res.nodes += 1;
+                    res.nodes - 1
+                });
+                res.edges.push((index[&b], next, Some(*c), *t));
+            }
+        }
+        res
+    }
+
+    /// Returns `true` if every state has a transition by every char of the alphabet,
+    /// i.e. of the set of all chars used in edges
+    pub fn is_complete(&self) -> bool {
+        let alphabet = BTreeSet::from_iter(self.edges.iter().filter_map(|e| e.2));
+        let mut out = vec![HashSet::new(); self.nodes];
+        for (a, _, c, _) in self.edges.iter() {
+            if let Some(c) = c {
+                out[*a].insert(*c);
+            }
+        }
+        out.iter().all(|o| o.len() == alphabet.len())
+    }
+
+    /// Makes transition function total over the alphabet, i.e. over the set of all chars used in edges.
+    /// All missing transitions are routed to a new non-final dead state.
+    /// If no transitions are missing, `self` is left unchanged
+    pub fn complete(&mut self) {
+        if self.is_complete() {
+            return;
+        }
+        let alphabet = BTreeSet::from_iter(self.edges.iter().filter_map(|e| e.2));
+        let mut out = vec![HashSet::new(); self.nodes];
+        for (a, _, c, _) in self.edges.iter() {
+            if let Some(c) = c {
+                out[*a].insert(*c);
+            }
+        }
+        let dead = self.nodes;
+        self.nodes += 1;
+        out.push(HashSet::new());
+        for (n, o) in out.iter().enumerate() {
+            for c in alphabet.iter().filter(|c| !o.contains(*c)) {
+                self.edges.push((n, dead, Some(*c), -1));
+            }
+        }
+    }
+
+    /// Builds automata, that accepts exactly those strings over the alphabet, that `self` rejects.
+    /// `self` must be deterministic and complete, see `SimpleAutomata::complete`
+    pub fn complement(&self) -> SimpleAutomata {
+        assert!(
+            self.is_deterministic(),
+            "complement() requires deterministic automata"
+        );
+        assert!(
+            self.is_complete(),
+            "complement() requires complete automata"
+        );
+        SimpleAutomata {
+            begin: self.begin,
+            nodes: self.nodes,
+            finals: HashSet::from_iter((0..self.nodes).filter(|n| !self.finals.contains(n))),
+            edges: self.edges.clone(),
+        }
+    }
+}
+
 impl<T: Automata> PartialEq<T> for SimpleAutomata {
     fn eq(&self, other: &T) -> bool {
         Automata::eq(self, other)
@@ -84,6 +1011,7 @@ impl<T: Automata> PartialEq<T> for SimpleAutomata {
 #[cfg(test)]
 mod automata_test {
     use super::*;
+    use crate::{Tdfa, Tnfa, UTnfa, parse};
 
     #[test]
     fn simple_test() {
@@ -118,4 +1046,796 @@ mod automata_test {
         assert_ne!(a, shifted);
         assert_ne!(shifted, a);
     }
+
+    #[test]
+    fn is_deterministic_test() {
+        let mut a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 2, Some(b'b'), -1),
+                (1, 2, Some(b'a'), -1),
+                (2, 2, Some(b'a'), -1),
+            ],
+        };
+        assert!(a.is_deterministic());
+
+        a.edges.push((0, 0, Some(b'a'), -1));
+        assert!(!a.is_deterministic());
+
+        a.edges.pop();
+        a.edges.push((1, 0, None, -1));
+        assert!(!a.is_deterministic());
+
+        let mut u = UTnfa::charset(Charset::from_char(b'a'));
+        assert!(u.is_deterministic());
+        u.concat(&UTnfa::tag(0));
+        assert!(!u.is_deterministic());
+    }
+
+    #[test]
+    fn minimize_test() {
+        // ab|cb, where both branches have their own states
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 5,
+            finals: HashSet::from([3, 4]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 2, Some(b'c'), -1),
+                (1, 3, Some(b'b'), -1),
+                (2, 4, Some(b'b'), -1),
+            ],
+        };
+        let expected = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 1, Some(b'c'), -1),
+                (1, 2, Some(b'b'), -1),
+            ],
+        };
+        let m = a.minimize();
+        assert_eq!(m, expected);
+        assert_eq!(m.finals, expected.finals);
+        assert_eq!(m.minimize(), expected);
+
+        // (a|b)*, with redundant states and an unreachable one
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 4,
+            finals: HashSet::from([0, 1, 2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 2, Some(b'b'), -1),
+                (1, 1, Some(b'a'), -1),
+                (1, 2, Some(b'b'), -1),
+                (2, 1, Some(b'a'), -1),
+                (2, 0, Some(b'b'), -1),
+                (3, 0, Some(b'a'), -1),
+            ],
+        };
+        let m = a.minimize();
+        assert_eq!(
+            m,
+            SimpleAutomata {
+                begin: 0,
+                nodes: 1,
+                finals: HashSet::from([0]),
+                edges: vec![(0, 0, Some(b'a'), -1), (0, 0, Some(b'b'), -1)],
+            }
+        );
+        assert!(m.is_final(0));
+    }
+
+    #[test]
+    fn minimize_tags_test() {
+        // a(c/5) | b(c/6), states 1 and 2 differ only by tags
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 4,
+            finals: HashSet::from([3]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 2, Some(b'b'), -1),
+                (1, 3, Some(b'c'), 5),
+                (2, 3, Some(b'c'), 6),
+            ],
+        };
+        let min = a.minimize();
+        assert_eq!(min.nodes, 4);
+        assert_eq!(min, a);
+        let mut tags = Vec::from_iter(min.edges.iter().map(|e| e.3));
+        tags.sort();
+        assert_eq!(tags, [-1, -1, 5, 6]);
+
+        // with the same tags, states 1 and 2 are merged
+        let mut a = a;
+        a.edges[3].3 = 5;
+        let min = a.minimize();
+        assert_eq!(min.nodes, 3);
+        assert_eq!(min.edges.len(), 3);
+        assert!(min.edges.iter().any(|e| e.2 == Some(b'c') && e.3 == 5));
+        assert!(min.accepts(b"ac") && min.accepts(b"bc") && !min.accepts(b"c"));
+
+        // tags of unreachable or dead states don't matter
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 4,
+            finals: HashSet::from([1]),
+            edges: vec![
+                (0, 1, Some(b'a'), 1),
+                (1, 2, Some(b'b'), 2),
+                (3, 1, Some(b'c'), 3),
+            ],
+        };
+        let min = a.minimize();
+        assert_eq!(min.nodes, 2);
+        assert_eq!(min.edges, [(0, 1, Some(b'a'), 1)]);
+    }
+
+    #[test]
+    fn complete_test() {
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (1, 2, Some(b'b'), -1),
+                (2, 2, Some(b'c'), -1),
+            ],
+        };
+        let mut c = a.clone();
+        c.complete();
+        assert_eq!(c.nodes, 4);
+        assert_eq!(c.finals, a.finals);
+        for n in 0..c.nodes {
+            let out = HashSet::<_>::from_iter(c.edges.iter().filter(|e| e.0 == n).map(|e| e.2));
+            assert_eq!(out, HashSet::from([Some(b'a'), Some(b'b'), Some(b'c')]));
+        }
+        assert!(c.is_deterministic());
+        assert_eq!(c.minimize(), a.minimize());
+
+        let mut d = c.clone();
+        d.complete();
+        assert_eq!(d.nodes, c.nodes);
+        assert_eq!(d.edges, c.edges);
+    }
+
+    #[test]
+    fn accepts_test() {
+        let mut a = UTnfa::charset(Charset::from_char(b'a'));
+        a.kleene();
+        a.concat(&UTnfa::charset(Charset::from_char(b'b')));
+        assert!(a.accepts(b"b"));
+        assert!(a.accepts(b"ab"));
+        assert!(a.accepts(b"aaab"));
+        assert!(!a.accepts(b""));
+        assert!(!a.accepts(b"aa"));
+        assert!(!a.accepts(b"abb"));
+        assert!(!a.accepts(b"ba"));
+    }
+
+    #[test]
+    fn empty_language_test() {
+        assert!(UTnfa::nothing().is_empty_language());
+        assert_eq!(UTnfa::nothing().shortest_match(), None);
+
+        let mut a = UTnfa::nothing();
+        a.union(&UTnfa::nothing());
+        assert!(a.is_empty_language());
+        a.union(&UTnfa::empty());
+        assert!(!a.is_empty_language());
+        assert_eq!(a.shortest_match(), Some(vec![]));
+
+        let a = UTnfa::charset(Charset::empty());
+        assert!(a.is_empty_language());
+        assert_eq!(a.shortest_match(), None);
+    }
+
+    #[test]
+    fn shortest_match_test() {
+        let char = |c| UTnfa::charset(Charset::from_char(c));
+        let mut ab = char(b'a');
+        ab.concat(&char(b'b'));
+        let mut abc = ab.clone();
+        abc.concat(&char(b'c'));
+        let mut a = abc.clone();
+        a.union(&ab);
+        assert!(!a.is_empty_language());
+        assert_eq!(a.shortest_match(), Some(b"ab".to_vec()));
+
+        let mut a = UTnfa::charset(Charset::from_range((b'x', b'z')));
+        a.union(&char(b'b'));
+        a.concat(&char(b'c'));
+        a.union(&abc);
+        assert_eq!(a.shortest_match(), Some(b"bc".to_vec()));
+
+        // final state is reached by `b` directly and by `a` through an epsilon edge
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 2, Some(b'b'), -1),
+                (1, 2, None, -1),
+            ],
+        };
+        assert!(a.accepts(b"a"));
+        assert_eq!(a.shortest_match(), Some(b"a".to_vec()));
+        let a = SimpleAutomata {
+            edges: vec![
+                (0, 2, Some(b'b'), -1),
+                (0, 1, Some(b'a'), -1),
+                (1, 3, None, -1),
+                (3, 2, None, -1),
+                (2, 2, None, -1),
+            ],
+            nodes: 4,
+            ..a
+        };
+        assert_eq!(a.shortest_match(), Some(b"a".to_vec()));
+    }
+
+    #[test]
+    fn step_test() {
+        // ab*c
+        let dfa = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (1, 1, Some(b'b'), 4),
+                (1, 2, Some(b'c'), -1),
+            ],
+        };
+        let mut state = dfa.begin();
+        for (c, next) in [(b'a', 1), (b'b', 1), (b'b', 1), (b'c', 2)] {
+            assert_eq!(dfa.step(state, c), [next]);
+            state = next;
+        }
+        assert!(dfa.is_final(state));
+        assert!(dfa.step(0, b'b').is_empty());
+        assert!(dfa.step(2, b'a').is_empty());
+        assert_eq!(
+            dfa.transitions_from(1),
+            [(Some(b'b'), 1, 4), (Some(b'c'), 2, -1)]
+        );
+        assert!(dfa.transitions_from(2).is_empty());
+
+        let nfa = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 2, Some(b'a'), -1),
+                (0, 1, Some(b'a'), -1),
+                (0, 1, None, 3),
+            ],
+        };
+        assert_eq!(nfa.step(0, b'a'), [1, 2]);
+        assert_eq!(nfa.transitions_from(0).len(), 3);
+        assert!(nfa.transitions_from(0).contains(&(None, 1, 3)));
+    }
+
+    #[test]
+    fn transition_table_test() {
+        // ab*
+        let dfa = SimpleAutomata {
+            begin: 0,
+            nodes: 2,
+            finals: HashSet::from([1]),
+            edges: vec![(0, 1, Some(b'a'), -1), (1, 1, Some(b'b'), 2)],
+        };
+        let (delta, finals) = dfa.transition_table();
+        assert_eq!(delta.len(), 2);
+        assert_eq!(delta[0][b'a' as usize], Some(1));
+        assert_eq!(delta[0][b'b' as usize], None);
+        assert_eq!(delta[1][b'b' as usize], Some(1));
+        assert_eq!(delta[1][b'a' as usize], None);
+        assert_eq!(delta.iter().flatten().flatten().count(), 2);
+        assert_eq!(finals, [false, true]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn transition_table_nondeterministic_test() {
+        (UTnfa::charset(Charset::from_char(b'a')) | UTnfa::empty()).transition_table();
+    }
+
+    #[test]
+    fn has_cycle_test() {
+        let c = |c| UTnfa::charset(Charset::from_char(c));
+        let mut a = c(b'a') + c(b'b') + c(b'c');
+        assert!(!a.has_cycle());
+        assert!(!(a.clone() | (c(b'd') + c(b'e'))).has_cycle());
+        a.kleene();
+        assert!(a.has_cycle());
+        assert!(parse("x(ab|c)+y").unwrap().has_cycle());
+        assert!(!parse("x(ab|c){1,3}y").unwrap().has_cycle());
+
+        // cycles, unreachable from the initial state, are ignored
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([1]),
+            edges: vec![(0, 1, Some(b'a'), -1), (2, 2, Some(b'a'), -1)],
+        };
+        assert!(!a.has_cycle());
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 1,
+            finals: HashSet::new(),
+            edges: vec![(0, 0, None, -1)],
+        };
+        assert!(a.has_cycle());
+    }
+
+    #[test]
+    fn degree_test() {
+        // 0 -a,b,c-> 1, 0 -eps-> 2, 1 -d-> 2
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from_iter([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 1, Some(b'b'), -1),
+                (0, 1, Some(b'c'), -1),
+                (0, 2, None, -1),
+                (1, 2, Some(b'd'), 1),
+            ],
+        };
+        assert_eq!(a.max_out_degree(), 4);
+        assert_eq!(a.total_edges(), 5);
+
+        let a = UTnfa::any_byte();
+        assert_eq!((a.max_out_degree(), a.total_edges()), (256, 256));
+        let a = UTnfa::empty();
+        assert_eq!((a.max_out_degree(), a.total_edges()), (0, 0));
+    }
+
+    #[test]
+    fn alphabet_test() {
+        let a = parse("[a-z]+").unwrap();
+        assert_eq!(a.alphabet(), Charset::from_range((b'a', b'z')));
+        let dfa = Tdfa::determinize(&Tnfa::from(&a));
+        assert_eq!(dfa.alphabet(), Charset::from_range((b'a', b'z')));
+        let a = parse("x|[0-9]y?").unwrap();
+        let expected = Charset::from_range((b'0', b'9')) | Charset::from_range((b'x', b'y'));
+        assert_eq!(a.alphabet(), expected);
+
+        let mut a = UTnfa::tag(2);
+        a.concat(&UTnfa::empty());
+        a.kleene();
+        assert!(a.alphabet().is_empty());
+        assert!(UTnfa::nothing().alphabet().is_empty());
+    }
+
+    #[test]
+    fn reachable_test() {
+        // 0 -> 1 <-> 2 -> 3, and a disconnected component 4 -> 5
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 6,
+            finals: HashSet::from_iter([3]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (1, 2, None, -1),
+                (2, 1, Some(b'b'), -1),
+                (2, 3, Some(b'c'), -1),
+                (4, 5, Some(b'd'), -1),
+            ],
+        };
+        assert_eq!(a.reachable_from(0), BTreeSet::from([0, 1, 2, 3]));
+        assert_eq!(a.reachable_from(2), BTreeSet::from([1, 2, 3]));
+        assert_eq!(a.reachable_from(4), BTreeSet::from([4, 5]));
+        assert_eq!(a.reachable_from(3), BTreeSet::from([3]));
+        assert_eq!(a.coreachable_to(3), BTreeSet::from([0, 1, 2, 3]));
+        assert_eq!(a.coreachable_to(1), BTreeSet::from([0, 1, 2]));
+        assert_eq!(a.coreachable_to(5), BTreeSet::from([4, 5]));
+        assert_eq!(a.coreachable_to(0), BTreeSet::from([0]));
+    }
+
+    #[test]
+    fn sccs_test() {
+        let c = |c| UTnfa::charset(Charset::from_char(c));
+        let a = c(b'a') + c(b'b') + c(b'c');
+        let sccs = a.sccs();
+        assert_eq!(sccs.len(), a.nodes());
+        assert!(sccs.iter().all(|s| s.len() == 1));
+
+        let mut a = c(b'a') + c(b'b');
+        a.kleene();
+        let a = c(b'x') + a + c(b'y');
+        let sccs = a.sccs();
+        assert_eq!(sccs.iter().map(|s| s.len()).sum::<usize>(), a.nodes());
+        let big = Vec::from_iter(sccs.iter().filter(|s| s.len() > 1));
+        assert_eq!(big.len(), 1);
+        // the loop contains both edges of `ab`, but not `x` or `y`
+        for ch in [b'a', b'b'] {
+            let e = a.list_edges().find(|e| e.2 == Some(ch)).unwrap();
+            assert!(big[0].contains(&e.0) && big[0].contains(&e.1));
+        }
+        for ch in [b'x', b'y'] {
+            let e = a.list_edges().find(|e| e.2 == Some(ch)).unwrap();
+            assert!(!big[0].contains(&e.0) || !big[0].contains(&e.1));
+        }
+
+        // 0 -> {1, 2} -> 3, components in reverse topological order
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 4,
+            finals: HashSet::new(),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (1, 2, None, -1),
+                (2, 1, Some(b'b'), -1),
+                (2, 3, Some(b'c'), -1),
+            ],
+        };
+        assert_eq!(a.sccs(), [vec![3], vec![1, 2], vec![0]]);
+    }
+
+    #[test]
+    fn range_edges_test() {
+        let a = UTnfa::any_byte();
+        assert_eq!(a.list_edges().count(), 256);
+        assert_eq!(
+            Vec::from_iter(a.list_range_edges()),
+            [(0, 1, Some(Charset::full()), -1)]
+        );
+
+        let a = UTnfa::any_char();
+        let bytes = a.list_edges().filter(|e| e.2.is_some()).count();
+        let ranges = a.list_range_edges().filter(|e| e.2.is_some()).count();
+        assert!(bytes > 400);
+        assert!(ranges < 30);
+        let dfa = Tdfa::determinize(&Tnfa::from(&a));
+        assert!(dfa.list_edges().count() > 400);
+        assert!(dfa.list_range_edges().count() < 30);
+
+        // the default implementation groups chars
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 2,
+            finals: HashSet::from([1]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 1, None, -1),
+                (0, 1, Some(b'c'), -1),
+                (0, 1, Some(b'b'), 2),
+            ],
+        };
+        assert_eq!(
+            Vec::from_iter(a.list_range_edges()),
+            [
+                (
+                    0,
+                    1,
+                    Some(Charset::from_char(b'a') | Charset::from_char(b'c')),
+                    -1
+                ),
+                (0, 1, Some(Charset::from_char(b'b')), 2),
+                (0, 1, None, -1),
+            ]
+        );
+    }
+
+    #[test]
+    fn final_states_test() {
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 5,
+            finals: HashSet::from([4, 1, 2]),
+            edges: vec![],
+        };
+        assert_eq!(a.final_states(), [1, 2, 4]);
+        assert_eq!(a.num_final_states(), 3);
+
+        let a = UTnfa::nothing();
+        assert_eq!(a.final_states(), [1]);
+        assert_eq!(a.num_final_states(), 1);
+    }
+
+    #[test]
+    fn eq_finals_test() {
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 2,
+            finals: HashSet::from([1]),
+            edges: vec![(0, 1, Some(b'a'), -1)],
+        };
+        let b = SimpleAutomata {
+            finals: HashSet::from([0]),
+            ..a.clone()
+        };
+        assert!(Automata::eq(&a, &a.clone()));
+        assert!(!Automata::eq(&a, &b) && !Automata::eq(&b, &a));
+        assert_ne!(a, b);
+        let c = SimpleAutomata {
+            finals: HashSet::from([0, 1]),
+            ..a.clone()
+        };
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn to_simple_test() {
+        let nfa = parse("(a|b)*c").unwrap();
+        let a = nfa.to_simple();
+        assert!(Automata::eq(&a, &nfa));
+        assert_eq!((a.begin(), a.nodes()), (nfa.begin(), nfa.nodes()));
+        assert_eq!(a.final_states(), nfa.final_states());
+        assert_eq!(a.edges, Vec::from_iter(nfa.list_edges()));
+        assert!(a.accepts(b"abac") && !a.accepts(b"ab"));
+
+        let dfa = Tdfa::determinize(&Tnfa::from(&nfa));
+        assert!(Automata::eq(&dfa.to_simple(), &dfa));
+        assert!(dfa.to_simple().minimize().accepts(b"bc"));
+
+        let nfa = parse("(a)|b").unwrap();
+        let a = nfa.to_untagged();
+        assert!(a.edges.iter().all(|e| e.3 == -1));
+        assert_eq!(a.edges.len(), nfa.list_edges().count());
+        assert_eq!(a.final_states(), nfa.final_states());
+        assert!(a.accepts(b"a") && a.accepts(b"b"));
+    }
+
+    #[test]
+    fn trace_test() {
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (1, 2, Some(b'b'), 5),
+                (2, 1, Some(b'a'), -1),
+            ],
+        };
+        assert_eq!(
+            a.trace(b"ab"),
+            Some(vec![(0, None, -1), (1, Some(b'a'), -1), (2, Some(b'b'), 5)])
+        );
+        assert_eq!(a.trace(b"abab").unwrap().len(), 5);
+        assert_eq!(a.trace(b"a"), None);
+        assert_eq!(a.trace(b"abb"), None);
+        assert_eq!(a.trace(b""), None);
+
+        let dfa = Tdfa::determinize(&Tnfa::from(&parse("a(b)").unwrap()));
+        let trace = dfa.trace(b"ab").unwrap();
+        assert_eq!(trace.iter().map(|e| e.0).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(dfa.tag_op(trace[1].2), [2]);
+        assert_eq!(dfa.tag_op(trace[2].2), [3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn trace_nondeterministic_test() {
+        parse("a|ab").unwrap().trace(b"a");
+    }
+
+    #[test]
+    fn states_test() {
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (1, 2, Some(b'b'), 4),
+                (0, 2, None, -1),
+                (1, 1, Some(b'c'), -1),
+            ],
+        };
+        let states = Vec::from_iter(a.states());
+        assert_eq!(states.len(), 3);
+        assert_eq!(
+            states[1],
+            StateInfo {
+                id: 1,
+                is_final: false,
+                out_edges: vec![(Some(b'b'), 2, 4), (Some(b'c'), 1, -1)],
+            }
+        );
+        assert_eq!(
+            states[0].out_edges,
+            vec![(Some(b'a'), 1, -1), (None, 2, -1)]
+        );
+        assert!(states[2].is_final && states[2].out_edges.is_empty());
+        for s in states {
+            assert_eq!(s.out_edges, a.transitions_from(s.id));
+        }
+    }
+
+    #[test]
+    fn to_regex_test() {
+        let dfa = |p| {
+            let dfa = Tdfa::determinize(&Tnfa::from(&parse(p).unwrap()));
+            SimpleAutomata {
+                begin: dfa.begin(),
+                nodes: dfa.nodes(),
+                finals: HashSet::from_iter(dfa.final_states()),
+                edges: Vec::from_iter(dfa.list_edges().map(|(a, b, c, _)| (a, b, c, -1))),
+            }
+            .minimize()
+        };
+        let a = dfa("a(b|c)");
+        let r = a.to_regex();
+        assert_eq!(r, "a[bc]");
+        assert!(crate::equivalent(&a, &crate::parse_bytes(&r).unwrap()));
+
+        for p in [
+            "(ab|c)*d?",
+            "x(a|b)*y|z+",
+            "(a*b)*|\\+\\x00\\xff",
+            "[^b]c|",
+            "((a|b)(c|d))*",
+        ] {
+            let a = dfa(p);
+            let r = a.to_regex();
+            assert!(
+                crate::equivalent(&a, &crate::parse_bytes(&r).unwrap()),
+                "{p}: {r}"
+            );
+        }
+
+        let empty = SimpleAutomata {
+            begin: 0,
+            nodes: 2,
+            finals: HashSet::from([1]),
+            edges: vec![(1, 0, Some(b'a'), -1)],
+        };
+        assert_eq!(empty.to_regex(), "[^\\x00-\\xff]");
+        assert!(
+            crate::parse_bytes(&empty.to_regex())
+                .unwrap()
+                .is_empty_language()
+        );
+        assert_eq!(dfa("").to_regex(), "(?:)");
+        assert_eq!(dfa("a*").to_regex(), "a*");
+    }
+
+    #[test]
+    fn to_debug_table_test() {
+        let a = SimpleAutomata {
+            begin: 1,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![
+                (1, 2, Some(b'c'), -1),
+                (1, 0, None, 3),
+                (1, 2, Some(b'a'), -1),
+                (0, 2, Some(b'x'), 4),
+                (1, 2, Some(b'b'), -1),
+                (2, 2, Some(0xff), -1),
+                (1, 0, Some(b' '), -1),
+            ],
+        };
+        assert_eq!(
+            a.to_debug_table(),
+            "\
+begin: 1
+0:
+  [x]/t=4 -> 2
+1:
+  [ ] -> 0
+  [abc] -> 2
+  ε/t=3 -> 0
+2 (final):
+  [\\xff] -> 2
+"
+        );
+
+        let a = UTnfa::from("ab");
+        assert_eq!(
+            a.to_debug_table(),
+            "begin: 4\n0:\n  [b] -> 1\n1 (final):\n2:\n  [a] -> 3\n3:\n  ε -> 0\n4:\n  ε -> 2\n"
+        );
+    }
+
+    #[test]
+    fn find_test() {
+        let a = parse("a+").unwrap();
+        assert_eq!(a.find(b"xaaax"), Some((1, 4)));
+        assert_eq!(a.find(b"aaa"), Some((0, 3)));
+        assert_eq!(a.find(b"xyz"), None);
+        assert_eq!(a.find(b""), None);
+        assert_eq!(
+            Vec::from_iter(a.find_iter(b"a_aa_aaa")),
+            [(0, 1), (2, 4), (5, 8)]
+        );
+
+        // leftmost match is preferred over the longer one
+        let a = parse("ab|bcde").unwrap();
+        assert_eq!(a.find(b"xabcde"), Some((1, 3)));
+        let a = parse("a|ab").unwrap();
+        assert_eq!(a.find(b"xab"), Some((1, 3)));
+
+        let a = parse("b*").unwrap();
+        assert_eq!(a.find(b"abb"), Some((0, 0)));
+        assert_eq!(
+            Vec::from_iter(a.find_iter(b"abba")),
+            [(0, 0), (1, 3), (3, 3), (4, 4)]
+        );
+    }
+
+    #[test]
+    fn matches_up_to_test() {
+        let char = |c| UTnfa::charset(Charset::from_char(c));
+        let mut a = char(b'a');
+        a.concat(&char(b'b'));
+        a.union(&char(b'c'));
+        assert_eq!(a.matches_up_to(2), vec![b"ab".to_vec(), b"c".to_vec()]);
+        assert_eq!(a.matches_up_to(1), vec![b"c".to_vec()]);
+        assert!(a.matches_up_to(0).is_empty());
+
+        let mut a = UTnfa::charset(Charset::from_range((b'a', b'b')));
+        a.kleene();
+        assert_eq!(
+            a.matches_up_to(2),
+            vec![
+                b"".to_vec(),
+                b"a".to_vec(),
+                b"aa".to_vec(),
+                b"ab".to_vec(),
+                b"b".to_vec(),
+                b"ba".to_vec(),
+                b"bb".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn complement_test() {
+        let mut a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from([2]),
+            edges: vec![(0, 1, Some(b'a'), -1), (1, 2, Some(b'b'), -1)],
+        };
+        a.complete();
+        let c = a.complement();
+        for s in [&b""[..], b"a", b"b", b"aa", b"ba", b"abb", b"aab", b"abab"] {
+            assert!(!a.accepts(s));
+            assert!(c.accepts(s));
+        }
+        assert!(a.accepts(b"ab"));
+        assert!(!c.accepts(b"ab"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn complement_incomplete_test() {
+        SimpleAutomata {
+            begin: 0,
+            nodes: 2,
+            finals: HashSet::from([1]),
+            edges: vec![(0, 1, Some(b'a'), -1)],
+        }
+        .complement();
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_test() {
+        let a = SimpleAutomata {
+            begin: 1,
+            nodes: 3,
+            finals: HashSet::from([0, 2]),
+            edges: vec![(1, 0, Some(b'a'), -1), (0, 2, None, 3)],
+        };
+        let json = serde_json::to_string(&a).unwrap();
+        let b: SimpleAutomata = serde_json::from_str(&json).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.finals, b.finals);
+    }
 }
@@ -1,12 +1,19 @@
-use std::fmt::Display;
+use core::char::TryFromCharError;
+use core::fmt::Display;
+use core::ops::BitOr;
 
-use derive_more::{BitOr, BitOrAssign};
+use derive_more::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
 use primitive_types::U256;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::UTnfa;
+use crate::collections::BTreeMap;
+use crate::prelude::*;
 
 /// Set of utf8-characters
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Utf8Charset {
     ranges: Vec<(char, char)>,
     invert: bool,
@@ -14,7 +21,7 @@ pub struct Utf8Charset {
 
 /// Set of single-byte characters, including `'\u{80}'..'\u{ff}'`.
 /// Multi-byte character can be represented as `Utf8Charset`
-#[derive(Clone, Copy, PartialEq, Debug, BitOr, BitOrAssign)]
+#[derive(Clone, Copy, PartialEq, Debug, BitOr, BitOrAssign, BitAnd, BitAndAssign, Not)]
 pub struct Charset {
     c: U256,
 }
@@ -38,16 +45,165 @@ impl Utf8Charset {
         self.add_range((c, c));
     }
 
+    /// Adds each char of `s` to `self`, unlike `Charset::from_str`, chars are taken literally
+    pub fn add_str(&mut self, s: &str) {
+        s.chars().for_each(|c| self.add_char(c));
+    }
+
     /// Adds all characters in range `range.0..=range.1` to `self`
     pub fn add_range(&mut self, range: (char, char)) {
         self.ranges.push(range);
     }
+
+    /// Returns `true` if `self` contains char `c`
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|(a, b)| (*a..=*b).contains(&c)) != self.invert
+    }
+
+    /// Returns `true` if `self` is inverted, i.e. contains all chars, except ones in its ranges
+    pub fn is_inverted(&self) -> bool {
+        self.invert
+    }
+
+    /// Returns normalized ranges of all chars, contained in `self`, with inversion applied
+    fn effective_ranges(&self) -> Vec<(char, char)> {
+        match self.invert {
+            false => normalize_ranges(&self.ranges),
+            true => subtract_ranges(&[('\0', char::MAX)], &self.ranges).into_vec(),
+        }
+    }
+
+    /// Returns non-inverted charset of all chars, contained in both `self` and `other`
+    pub fn intersect(&self, other: &Utf8Charset) -> Utf8Charset {
+        let (a, b) = (self.effective_ranges(), other.effective_ranges());
+        let (mut i, mut j) = (0, 0);
+        let mut res = Utf8Charset::empty();
+        while i < a.len() && j < b.len() {
+            if let Some(r) = intersect_ranges(a[i], b[j]) {
+                res.add_range(r);
+            }
+            match a[i].1 < b[j].1 {
+                true => i += 1,
+                false => j += 1,
+            }
+        }
+        res
+    }
+
+    /// Returns charset, where each char of `self` is complemented with its simple case counterparts,
+    /// i.e. with its lowercase and uppercase mappings, that consist of a single char.
+    /// Folding is applied before inversion, so `[^a]` becomes `[^aA]`
+    pub fn case_fold(&self) -> Utf8Charset {
+        let mut res = self.clone();
+        for (a, b) in normalize_ranges(&self.ranges) {
+            // there are no cased chars after it
+            for c in (a..=b).take_while(|c| *c <= LAST_CASED_CHAR) {
+                let (mut lower, mut upper) = (c.to_lowercase(), c.to_uppercase());
+                for f in [(lower.next(), lower.next()), (upper.next(), upper.next())] {
+                    if let (Some(f), None) = f {
+                        res.add_char(f);
+                    }
+                }
+            }
+        }
+        res.ranges = normalize_ranges(&res.ranges);
+        res
+    }
+
+    /// Returns iterator over ranges, added to `self`, regardless of inversion.
+    /// Ranges are normalized: sorted, with overlapping and adjacent ones merged
+    pub fn iter_ranges(&self) -> impl Iterator<Item = (char, char)> {
+        normalize_ranges(&self.ranges).into_iter()
+    }
 }
 
+/// Charsets are equal if they contain the same chars, regardless of how they were built
+impl PartialEq for Utf8Charset {
+    fn eq(&self, other: &Self) -> bool {
+        self.effective_ranges() == other.effective_ranges()
+    }
+}
+
+impl Eq for Utf8Charset {}
+
+/// Collects non-inverted charset from ranges
+impl FromIterator<(char, char)> for Utf8Charset {
+    fn from_iter<T: IntoIterator<Item = (char, char)>>(iter: T) -> Self {
+        Self {
+            ranges: Vec::from_iter(iter),
+            invert: false,
+        }
+    }
+}
+
+/// Adds ranges to `self` like `add_range`, inversion is kept
+impl Extend<(char, char)> for Utf8Charset {
+    fn extend<T: IntoIterator<Item = (char, char)>>(&mut self, iter: T) {
+        self.ranges.extend(iter);
+    }
+}
+
+/// Adds chars to `self` like `add_char`, inversion is kept
+impl Extend<char> for Utf8Charset {
+    fn extend<T: IntoIterator<Item = char>>(&mut self, iter: T) {
+        self.ranges.extend(iter.into_iter().map(|c| (c, c)));
+    }
+}
+
+impl Default for Utf8Charset {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+/// The last char, that has a lowercase or uppercase mapping
+const LAST_CASED_CHAR: char = '\u{1e943}';
+
 impl Charset {
+    /// Empty charset, usable in `const` contexts
+    pub const EMPTY: Charset = Charset { c: U256([0; 4]) };
+
     /// Creates an empty charset
     pub fn empty() -> Self {
-        Self { c: U256::zero() }
+        Self::EMPTY
+    }
+
+    /// Creates a charset, that contains all 256 characters
+    pub fn full() -> Self {
+        Self { c: !U256::zero() }
+    }
+
+    /// Creates a charset of ascii digits, i.e. `\d`
+    pub fn digit() -> Self {
+        Self::from_range((b'0', b'9'))
+    }
+
+    /// Creates a charset of ascii word chars, i.e. `\w`
+    pub fn word() -> Self {
+        Self::digit()
+            | Self::from_range((b'a', b'z'))
+            | Self::from_range((b'A', b'Z'))
+            | Self::from_char(b'_')
+    }
+
+    /// Creates a charset of ascii whitespace chars, i.e. `\s`
+    pub fn whitespace() -> Self {
+        Self::from_range((b'\t', b'\r')) | Self::from_char(b' ')
+    }
+
+    /// Creates a charset of all bytes, that are not ascii digits, i.e. `\D`
+    pub fn non_digit() -> Self {
+        !Self::digit()
+    }
+
+    /// Creates a charset of all bytes, that are not ascii word chars, i.e. `\W`
+    pub fn non_word() -> Self {
+        !Self::word()
+    }
+
+    /// Creates a charset of all bytes, that are not ascii whitespace chars, i.e. `\S`
+    pub fn non_whitespace() -> Self {
+        !Self::whitespace()
     }
 
     /// Creates a charset, that contains character `c`
@@ -57,11 +213,34 @@ impl Charset {
 
     /// Creates a charset, that contains all characters within `r.0..=r.1`
     pub fn from_range(r: (u8, u8)) -> Self {
-        let mut s = Self::empty();
-        for c in r.0..=r.1 {
-            s.c |= U256::one() << c;
+        Self::from_range_const(r.0, r.1)
+    }
+
+    /// Same as `from_range`, but usable in `const` contexts
+    pub const fn from_range_const(lo: u8, hi: u8) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut c = lo as usize;
+        while c <= hi as usize {
+            limbs[c / 64] |= 1 << (c % 64);
+            c += 1;
+        }
+        Self { c: U256(limbs) }
+    }
+
+    /// Creates a charset, that contains all characters within each of `ranges`,
+    /// same as uniting `from_range` of each of them, but without intermediate charsets
+    pub const fn from_ranges(ranges: &[(u8, u8)]) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut i = 0;
+        while i < ranges.len() {
+            let mut c = ranges[i].0 as usize;
+            while c <= ranges[i].1 as usize {
+                limbs[c / 64] |= 1 << (c % 64);
+                c += 1;
+            }
+            i += 1;
         }
-        s
+        Self { c: U256(limbs) }
     }
 
     /// Returns iterator over all chars, contained within charset
@@ -69,14 +248,255 @@ impl Charset {
         CharsetIter { c: *self, i: 0 }
     }
 
+    /// Returns lazy iterator over maximal ranges `(lo, hi)` of chars in `self`, in increasing order.
+    /// Iterator is double-ended, so ranges may be taken from both ends
+    pub fn iter_ranges(&self) -> impl DoubleEndedIterator<Item = (u8, u8)> + use<> {
+        RangesIter {
+            c: *self,
+            lo: 0,
+            hi: 256,
+        }
+    }
+
+    /// Returns maximal ranges `(lo, hi)` of chars in `self`, in increasing order
+    pub fn ranges(&self) -> Vec<(u8, u8)> {
+        Vec::from_iter(self.iter_ranges())
+    }
+
     /// Returns `true` if `self` contains char `c`
     pub fn contains(&self, c: u8) -> bool {
         (self.c & (U256::one() << c)) != U256::zero()
     }
+
+    /// Returns `true` if `self` contains all characters within `r.0..=r.1`
+    pub fn contains_range(&self, r: (u8, u8)) -> bool {
+        let range = Charset::from_range(r);
+        (*self & range) == range
+    }
+
+    /// Returns charset, where each ascii letter of `self` is complemented with its other case.
+    /// Bytes `0x80..=0xff` are kept as is, see `Utf8Charset::case_fold` for non-ascii letters
+    pub fn case_fold(&self) -> Self {
+        let letters = *self & (Self::from_range((b'a', b'z')) | Self::from_range((b'A', b'Z')));
+        letters
+            .iter()
+            .fold(*self, |c, l| c | Self::from_char(l ^ 0x20))
+    }
+
+    /// Returns `true` if `self` and `other` have a common char, same as `!(self & other).is_empty()`
+    pub fn intersects(&self, other: &Charset) -> bool {
+        self.c
+            .0
+            .iter()
+            .zip(other.c.0.iter())
+            .any(|(a, b)| a & b != 0)
+    }
+
+    /// Calls `f` for each char of `self` in ascending order, same as `iter().for_each(f)`,
+    /// but limbs of the bitmask are scanned directly, skipping zero bits
+    pub fn for_each<F: FnMut(u8)>(&self, mut f: F) {
+        for (i, limb) in self.c.0.iter().enumerate() {
+            let mut bits = *limb;
+            while bits != 0 {
+                f((64 * i) as u8 + bits.trailing_zeros() as u8);
+                bits &= bits - 1;
+            }
+        }
+    }
+
+    /// Returns number of chars in `self`
+    pub fn len(&self) -> usize {
+        self.c.0.iter().map(|limb| limb.count_ones() as usize).sum()
+    }
+
+    /// Returns charset of all chars, that are not contained in `self`, same as `!self`
+    pub fn complement(&self) -> Self {
+        !*self
+    }
+
+    /// Returns charset of all chars within `lo..=hi`, that are not contained in `self`
+    pub fn invert_within(&self, lo: u8, hi: u8) -> Self {
+        Self::from_range((lo, hi)) & !*self
+    }
+
+    /// Converts class `uc` of chars `'\0'..='\u{ff}'` into charset of the same bytes.
+    /// If `uc` is inverted, it is inverted within `0..=255`, i.e. `[^a]` becomes all bytes except `a`.
+    /// Returns `None` if any range of `uc` contains a multi-byte char
+    pub fn from_utf8_byte_class(uc: &Utf8Charset) -> Option<Charset> {
+        let mut res = Charset::empty();
+        for (a, b) in uc.iter_ranges().filter(|(a, b)| a <= b) {
+            res |= Charset::from_range((u8::try_from(a).ok()?, u8::try_from(b).ok()?));
+        }
+        Some(if uc.is_inverted() { !res } else { res })
+    }
+
+    /// Returns `true` if `self` contains no chars
+    pub fn is_empty(&self) -> bool {
+        self.c.is_zero()
+    }
+
+    /// Returns the smallest char in `self`, or `None` if `self` is empty
+    pub fn first(&self) -> Option<u8> {
+        match self.is_empty() {
+            true => None,
+            false => Some(self.c.trailing_zeros() as u8),
+        }
+    }
+
+    /// Returns the largest char in `self`, or `None` if `self` is empty
+    pub fn last(&self) -> Option<u8> {
+        match self.is_empty() {
+            true => None,
+            false => Some(255 - self.c.leading_zeros() as u8),
+        }
+    }
+
+    /// Splits union of `sets` into the minimal number of disjoint non-empty charsets,
+    /// s.t. each of `sets` is a union of some of them.
+    /// Two chars fall into the same part iff they belong to exactly the same `sets`
+    pub fn disjoint_partition(sets: &[Charset]) -> Vec<Charset> {
+        let mut parts: Vec<Charset> = Vec::new();
+        for s in sets {
+            let mut rest = *s;
+            let mut next = Vec::with_capacity(parts.len() + 1);
+            for p in parts {
+                if !p.intersects(s) {
+                    next.push(p);
+                    continue;
+                }
+                next.extend([p & *s, p & !*s].into_iter().filter(|p| !p.is_empty()));
+                rest &= !p;
+            }
+            if !rest.is_empty() {
+                next.push(rest);
+            }
+            parts = next;
+        }
+        parts
+    }
+
+    /// Returns bitmask of `self` as 64 lowercase hex digits, most significant first,
+    /// i.e. char `c` is the `c`-th bit from the end
+    pub fn to_hex(&self) -> String {
+        self.c
+            .0
+            .iter()
+            .rev()
+            .map(|limb| format!("{limb:016x}"))
+            .collect()
+    }
+
+    /// Parses charset from 64 hex digits, as returned by `to_hex`. Both cases are accepted
+    pub fn from_hex(s: &str) -> Result<Self, FromHexError> {
+        if s.len() != 64 || !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(FromHexError);
+        }
+        let mut limbs = [0; 4];
+        for (i, limb) in limbs.iter_mut().rev().enumerate() {
+            *limb = u64::from_str_radix(&s[16 * i..16 * i + 16], 16).map_err(|_| FromHexError)?;
+        }
+        Ok(Self { c: U256(limbs) })
+    }
+
+    /// Renders `self` as a body of regex class without brackets, e.g. `\-0-9a-z`.
+    /// All ASCII punctuation is escaped, so the body of a single char is a valid regex literal
+    pub(crate) fn regex_body(&self) -> String {
+        let regex_char = |c: u8| match c {
+            c if c.is_ascii_punctuation() => format!("\\{}", c as char),
+            b' '..b'\x7f' => (c as char).to_string(),
+            _ => format!("\\x{c:02x}"),
+        };
+        let ranges = self.iter_ranges().map(|(lo, hi)| match hi - lo {
+            0 => regex_char(lo),
+            1 => regex_char(lo) + &regex_char(hi),
+            _ => format!("{}-{}", regex_char(lo), regex_char(hi)),
+        });
+        String::from_iter(ranges)
+    }
+
+    /// Renders `self` in the given style, see `DisplayStyle`
+    pub fn display_as(&self, style: DisplayStyle) -> String {
+        let rust_char = |c: u8| match c {
+            b'\\' | b'\'' => format!("b'\\{}'", c as char),
+            b' '..b'\x7f' => format!("b'{}'", c as char),
+            _ => format!("b'\\x{c:02x}'"),
+        };
+        match style {
+            DisplayStyle::Regex => format!("[{}]", self.regex_body()),
+            DisplayStyle::RustByteLiteral => {
+                let ranges = self.iter_ranges().map(|(lo, hi)| match lo == hi {
+                    true => rust_char(lo),
+                    false => format!("{}..={}", rust_char(lo), rust_char(hi)),
+                });
+                Vec::from_iter(ranges).join(" | ")
+            }
+            DisplayStyle::Verbose => String::from_iter(self.iter().map(|c| format!("\\x{c:02x}"))),
+        }
+    }
+}
+
+/// Style of `Charset::display_as`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayStyle {
+    /// Regex class, e.g. `[\-0-9a-z]`, where all ASCII punctuation is escaped. Its body, without brackets, can be parsed by `Charset::from_str`
+    Regex,
+    /// Rust pattern of byte literals, e.g. `b'-' | b'0'..=b'9'`, usable in `match` arms.
+    /// Empty charset gives an empty string, which is not a valid pattern, so it must be skipped.
+    /// Used by `codegen::emit_rust`
+    RustByteLiteral,
+    /// Each byte escaped as `\xNN`, e.g. `\x2d\x30\x31`
+    Verbose,
+}
+
+/// Error, returned by `Charset::from_hex` if string is not exactly 64 hex digits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FromHexError;
+
+impl Display for FromHexError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("charset must be exactly 64 hex digits")
+    }
+}
+
+impl core::error::Error for FromHexError {}
+
+/// Creates charset of a single char `c`, if `c <= '\u{ff}'`, just like `u8::try_from(c)`.
+/// Note, that chars `'\u{80}'..='\u{ff}'` are converted to a single byte, not their utf-8
+/// representation, use `Utf8Charset` for multi-byte chars
+impl TryFrom<char> for Charset {
+    type Error = TryFromCharError;
+
+    fn try_from(c: char) -> Result<Self, TryFromCharError> {
+        u8::try_from(c).map(Self::from_char)
+    }
+}
+
+/// Adds char to charset, same as `c | Charset::from_char(b)`
+impl BitOr<u8> for Charset {
+    type Output = Charset;
+
+    fn bitor(self, c: u8) -> Charset {
+        self | Charset::from_char(c)
+    }
+}
+
+/// Adds range of chars to charset, same as `c | Charset::from_range(r)`
+impl BitOr<(u8, u8)> for Charset {
+    type Output = Charset;
+
+    fn bitor(self, r: (u8, u8)) -> Charset {
+        self | Charset::from_range(r)
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Self::empty()
+    }
 }
 
 impl Display for Charset {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         for c in self.iter() {
             match c {
                 b' '..b'\x7f' => write!(f, "{}", c as char)?,
@@ -87,11 +507,39 @@ impl Display for Charset {
     }
 }
 
-struct CharsetIter {
+/// Charset is serialized as 32 little-endian bytes of its bitmask
+#[cfg(feature = "serde")]
+impl Serialize for Charset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.c.to_little_endian().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Charset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = <[u8; 32]>::deserialize(deserializer)?;
+        Ok(Self {
+            c: U256::from_little_endian(&bytes),
+        })
+    }
+}
+
+/// Iterator over chars of `Charset` in increasing order
+pub struct CharsetIter {
     c: Charset,
     i: usize,
 }
 
+impl IntoIterator for &Charset {
+    type Item = u8;
+    type IntoIter = CharsetIter;
+
+    fn into_iter(self) -> CharsetIter {
+        CharsetIter { c: *self, i: 0 }
+    }
+}
+
 impl Iterator for CharsetIter {
     type Item = u8;
 
@@ -107,6 +555,47 @@ impl Iterator for CharsetIter {
     }
 }
 
+/// Iterator over maximal ranges of `Charset`, only chars within `lo..hi` are not yielded yet
+struct RangesIter {
+    c: Charset,
+    lo: usize,
+    hi: usize,
+}
+
+impl RangesIter {
+    /// Returns chars within `lo..hi`
+    fn rest(&self) -> Charset {
+        match self.lo < self.hi {
+            true => Charset::from_range((self.lo as u8, (self.hi - 1) as u8)),
+            false => Charset::empty(),
+        }
+    }
+}
+
+impl Iterator for RangesIter {
+    type Item = (u8, u8);
+
+    fn next(&mut self) -> Option<(u8, u8)> {
+        let rest = self.rest();
+        let lo = (self.c & rest).first()?;
+        let gap = !self.c & rest & !Charset::from_range((0, lo));
+        let hi = gap.first().map_or(self.hi - 1, |c| c as usize - 1);
+        self.lo = hi + 1;
+        Some((lo, hi as u8))
+    }
+}
+
+impl DoubleEndedIterator for RangesIter {
+    fn next_back(&mut self) -> Option<(u8, u8)> {
+        let rest = self.rest();
+        let hi = (self.c & rest).last()?;
+        let gap = !self.c & rest & !Charset::from_range((hi, 255));
+        let lo = gap.last().map_or(self.lo, |c| c as usize + 1);
+        self.hi = lo;
+        Some((lo as u8, hi))
+    }
+}
+
 /// Creates charset
 #[macro_export]
 macro_rules! charset {
@@ -127,26 +616,88 @@ macro_rules! charset {
     }
 }
 
+/// Creates single-byte charset, i.e. `Charset`, without utf-8 expansion
+#[macro_export]
+macro_rules! byteset {
+    (^ $($t:tt)*) => {
+        !$crate::byteset!($($t)*)
+    };
+    (@add $c:ident;) => {};
+    (@add $c:ident; $a:literal - $b:literal $($t:tt)*) => {
+        $c |= $crate::Charset::from_range(($a, $b));
+        $crate::byteset!(@add $c; $($t)*)
+    };
+    (@add $c:ident; $a:literal $($t:tt)*) => {
+        $c |= $crate::Charset::from_char($a);
+        $crate::byteset!(@add $c; $($t)*)
+    };
+    ($($t:tt)*) => {
+        {
+            #[allow(unused_mut)]
+            let mut c = $crate::Charset::empty();
+            $crate::byteset!(@add c; $($t)*);
+            c
+        }
+    };
+}
+
 // Following code implements Into<UTnfa> for Utf8Charset
 
-const UTF8_RANGES: [(char, char); 4] = [
+/// Ranges of chars, that have utf-8 representations of the same length.
+/// 3-byte block is splitted around surrogates `'\u{d800}'..='\u{dfff}'`, which are not chars
+const UTF8_RANGES: [(char, char); 5] = [
     ('\u{000000}', '\u{00007f}'),
     ('\u{000080}', '\u{0007ff}'),
-    ('\u{000800}', '\u{00ffff}'),
+    ('\u{000800}', '\u{00d7ff}'),
+    ('\u{00e000}', '\u{00ffff}'),
     ('\u{010000}', '\u{10ffff}'),
 ];
 
 /// Calculates intersection between 2 character ranges
 /// If it the result is empty `None` is returned
 fn intersect_ranges(a: (char, char), b: (char, char)) -> Option<(char, char)> {
-    let (a, b) = (std::cmp::max(a.0, b.0), std::cmp::min(a.1, b.1));
+    let (a, b) = (core::cmp::max(a.0, b.0), core::cmp::min(a.1, b.1));
     if a > b { None } else { Some((a, b)) }
 }
 
+/// Returns the char, preceding `c`. Surrogates are skipped, as they are not chars
+fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{e000}' => Some('\u{d7ff}'),
+        _ => char::from_u32((c as u32).checked_sub(1)?),
+    }
+}
+
+/// Returns the char, following `c`. Surrogates are skipped, as they are not chars
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// Sorts ranges and merges overlapping and adjacent ones, empty ranges are dropped
+fn normalize_ranges(r: &[(char, char)]) -> Vec<(char, char)> {
+    let mut r = Vec::from_iter(r.iter().copied().filter(|(a, b)| a <= b));
+    r.sort();
+    let mut res: Vec<(char, char)> = Vec::with_capacity(r.len());
+    for (a, b) in r {
+        match res.last_mut() {
+            Some(last) if next_char(last.1).is_none_or(|n| a <= n) => {
+                last.1 = core::cmp::max(last.1, b)
+            }
+            _ => res.push((a, b)),
+        }
+    }
+    res
+}
+
 /// Subtracts ranges `sub` from original range `a` and returns resulting list of ranges
+/// Both `a` and `sub` are normalized first, so the result is sorted and doesn't depend on order of ranges.
 /// Here we are using a simple dp to iteratively calculate result
 fn subtract_ranges(a: &[(char, char)], sub: &[(char, char)]) -> Box<[(char, char)]> {
-    let mut dp = [Vec::from_iter(a.iter().map(|a| *a)), Vec::new()];
+    let sub = normalize_ranges(sub);
+    let mut dp = [normalize_ranges(a), Vec::new()];
     for (i, s) in sub.iter().enumerate().map(|(i, s)| (i % 2, s)) {
         dp[i ^ 1].clear();
         for j in 0..dp[i].len() {
@@ -154,13 +705,12 @@ fn subtract_ranges(a: &[(char, char)], sub: &[(char, char)]) -> Box<[(char, char
             match intersect_ranges(old, *s) {
                 None => dp[i ^ 1].push(old),
                 Some(s) => {
-                    if old.0 < s.0 {
-                        // SAFETY: old.0 is a valid character and old.0 < s.0
-                        dp[i ^ 1].push((old.0, unsafe { char::from_u32_unchecked(s.0 as u32 - 1) }))
+                    // there is a char before s.0 (after s.1), as old.0 < s.0 (old.1 > s.1)
+                    if let Some(prev) = prev_char(s.0).filter(|_| old.0 < s.0) {
+                        dp[i ^ 1].push((old.0, prev))
                     }
-                    if old.1 > s.1 {
-                        // SAFETY: old.1 is a valid character and old.1 > s.1
-                        dp[i ^ 1].push((unsafe { char::from_u32_unchecked(s.1 as u32 + 1) }, old.1))
+                    if let Some(next) = next_char(s.1).filter(|_| old.1 > s.1) {
+                        dp[i ^ 1].push((next, old.1))
                     }
                 }
             }
@@ -177,57 +727,108 @@ fn subtract_ranges(a: &[(char, char)], sub: &[(char, char)]) -> Box<[(char, char
     }
 }
 
-/// Creates UTnfa from character range
+/// Appends to `res` sequences of byte ranges, that match utf-8 representations of chars in `a..=b`.
 /// Algorithm:
-/// 1. Ranges are splitted into smaller ranges, s.t. utf-8 representations all
-/// characters in the same range have the same byte length
-/// 2. For each range, a UTnfa is created (by concatenating UTnfa for Charsets for each byte)
-/// 3. Theese UTnfa's are united
-fn multibyte_range(a: char, b: char) -> UTnfa {
-    let r = [
-        intersect_ranges((a, b), UTF8_RANGES[0]),
-        intersect_ranges((a, b), UTF8_RANGES[1]),
-        intersect_ranges((a, b), UTF8_RANGES[2]),
-        intersect_ranges((a, b), UTF8_RANGES[3]),
-    ];
-
-    let mut res = UTnfa::empty();
-    for (count, r) in r.iter().enumerate().map(|(i, r)| (i + 1, r)) {
-        match r {
-            None => continue,
-            Some((a, b)) => {
-                let mut g = ([0; 4], [0, 4]);
-                let mut u = UTnfa::empty();
-                a.encode_utf8(&mut g.0);
-                b.encode_utf8(&mut g.1);
-                for i in 0..count {
-                    u.concat(&UTnfa::charset(Charset::from_range((g.0[i], g.1[i]))));
-                }
-                res.union(&u);
-            }
+/// 1. Range is splitted into smaller ranges, s.t. utf-8 representations all
+///    characters in the same range have the same byte length
+/// 2. Each range is splitted further by `utf8_sequences`, until it is a product of byte ranges
+fn multibyte_sequences(a: char, b: char, res: &mut Vec<Vec<(u8, u8)>>) {
+    for r in UTF8_RANGES.iter() {
+        if let Some((a, b)) = intersect_ranges((a, b), *r) {
+            utf8_sequences(a as u32, b as u32, a.len_utf8(), res);
         }
     }
-
-    UTnfa::empty()
 }
 
-impl Into<UTnfa> for Utf8Charset {
-    fn into(self) -> UTnfa {
-        let mut ranges = self.ranges.into_boxed_slice();
-        if self.invert {
-            ranges = subtract_ranges(&UTF8_RANGES, &ranges)
+/// Appends to `res` sequences of byte ranges, that match utf-8 representations
+/// of all code points in `a..=b`. All code points in `a..=b` should be encoded with `len` bytes.
+/// If `a` and `b` differ in some leading bytes, the range is splitted at the boundary
+/// of the next trailing byte, s.t. each part is a product of byte ranges
+fn utf8_sequences(a: u32, b: u32, len: usize, res: &mut Vec<Vec<(u8, u8)>>) {
+    for i in 1..len {
+        let m = (1 << (6 * i)) - 1;
+        if a & !m == b & !m {
+            continue;
         }
-        let mut res = UTnfa::empty();
-        for range in ranges {
-            res.union(&multibyte_range(range.0, range.1));
+        if a & m != 0 {
+            utf8_sequences(a, a | m, len, res);
+            utf8_sequences((a | m) + 1, b, len, res);
+            return;
+        }
+        if b & m != m {
+            utf8_sequences(a, (b & !m) - 1, len, res);
+            utf8_sequences(b & !m, b, len, res);
+            return;
         }
-        res
+    }
+    let (a, b) = (encode_utf8(a, len), encode_utf8(b, len));
+    res.push(Vec::from_iter((0..len).map(|i| (a[i], b[i]))));
+}
+
+/// Creates UTnfa, that matches any of sequences of byte ranges `seqs`.
+/// Sequences with equal suffixes share states, that match these suffixes, e.g. all trailing
+/// continuation bytes `[80-bf]` lead to the same state, so ranges, that span many leading bytes,
+/// don't blow up the automata. Edges, that leave the initial state to the same state, are merged
+fn sequences_utnfa(seqs: &[Vec<(u8, u8)>]) -> UTnfa {
+    // node 0 is the initial state, node 1 is the final one
+    let mut nodes = 2;
+    let mut edges = Vec::new();
+    let mut suffixes: BTreeMap<&[(u8, u8)], usize> = BTreeMap::new();
+    let mut first: BTreeMap<usize, Charset> = BTreeMap::new();
+    for seq in seqs {
+        let mut to = 1;
+        for k in (1..seq.len()).rev() {
+            to = *suffixes.entry(&seq[k..]).or_insert_with(|| {
+                edges.push((nodes, to, Charset::from_range(seq[k])));
+                nodes += 1;
+                nodes - 1
+            });
+        }
+        *first.entry(to).or_default() |= Charset::from_range(seq[0]);
+    }
+    edges.extend(first.into_iter().map(|(to, c)| (0, to, c)));
+    UTnfa::from_edges(nodes, edges)
+}
+
+/// Encodes code point `c` into `len` bytes of utf-8. Unlike `char::encode_utf8`,
+/// works for any code point, including surrogates
+fn encode_utf8(c: u32, len: usize) -> [u8; 4] {
+    let tail = |i: usize| 0x80 | (c >> (6 * i) & 0x3f) as u8;
+    match len {
+        1 => [c as u8, 0, 0, 0],
+        2 => [0xc0 | (c >> 6) as u8, tail(0), 0, 0],
+        3 => [0xe0 | (c >> 12) as u8, tail(1), tail(0), 0],
+        _ => [0xf0 | (c >> 18) as u8, tail(2), tail(1), tail(0)],
+    }
+}
+
+/// Creates UTnfa, that matches utf-8 representation of any char within `ranges`,
+/// or of any char outside of them if `invert` is `true`.
+/// Ranges may be unsorted and overlapping, empty ranges are ignored.
+/// Unless `invert` is set, ranges are consumed one by one without being collected
+pub fn utnfa_from_ranges(ranges: impl IntoIterator<Item = (char, char)>, invert: bool) -> UTnfa {
+    let mut seqs = Vec::new();
+    match invert {
+        false => ranges
+            .into_iter()
+            .for_each(|r| multibyte_sequences(r.0, r.1, &mut seqs)),
+        true => subtract_ranges(&UTF8_RANGES, &Vec::from_iter(ranges))
+            .iter()
+            .for_each(|r| multibyte_sequences(r.0, r.1, &mut seqs)),
+    }
+    sequences_utnfa(&seqs)
+}
+
+impl From<Utf8Charset> for UTnfa {
+    fn from(c: Utf8Charset) -> Self {
+        utnfa_from_ranges(c.ranges, c.invert)
     }
 }
 
 #[cfg(test)]
 mod charset_test {
     use super::*;
+    use crate::Automata;
 
     #[test]
     fn charset_basic_test() {
@@ -235,7 +836,7 @@ mod charset_test {
         let v: Vec<u8> = c.iter().collect();
         assert_eq!(v.as_slice(), b"123456789");
         for i in 0..=255 {
-            assert_eq!(c.contains(i), i >= b'1' && i <= b'9');
+            assert_eq!(c.contains(i), (b'1'..=b'9').contains(&i));
         }
 
         let c = Charset::from_range((0, 255));
@@ -258,6 +859,566 @@ mod charset_test {
         assert_eq!(Charset::from_char(b'a').to_string().as_str(), "a");
     }
 
+    #[test]
+    fn full_test() {
+        let full = Charset::full();
+        assert_eq!(full.len(), 256);
+        assert!((0..=255).all(|c| full.contains(c)));
+        assert_eq!(full, Charset::from_range((0, 255)));
+        assert_eq!(full.complement(), Charset::empty());
+        assert_eq!(Charset::empty().complement(), full);
+        assert_eq!(Charset::empty().len(), 0);
+
+        let c = Charset::from_range((b'a', b'z'));
+        assert_eq!(c.len(), 26);
+        assert_eq!(c.complement().len(), 230);
+        assert_eq!(c.complement() | c, full);
+    }
+
+    #[test]
+    fn charset_ranges_test() {
+        for c in [
+            Charset::empty(),
+            Charset::full(),
+            Charset::word(),
+            Charset::whitespace(),
+            Charset::from_char(0) | Charset::from_char(255),
+            Charset::from_range((60, 70)) | Charset::from_range((127, 129)),
+        ] {
+            let mut naive: Vec<(u8, u8)> = Vec::new();
+            for x in c.iter() {
+                match naive.last_mut() {
+                    Some(r) if r.1 as usize + 1 == x as usize => r.1 = x,
+                    _ => naive.push((x, x)),
+                }
+            }
+            assert_eq!(c.ranges(), naive);
+            assert!(c.iter_ranges().eq(naive.iter().copied()));
+            assert!(c.iter_ranges().rev().eq(naive.iter().rev().copied()));
+        }
+
+        let mut it = Charset::word().iter_ranges();
+        assert_eq!(it.next(), Some((b'0', b'9')));
+        assert_eq!(it.next_back(), Some((b'a', b'z')));
+        assert_eq!(it.next_back(), Some((b'_', b'_')));
+        assert_eq!(it.next(), Some((b'A', b'Z')));
+        assert_eq!(it.next(), None);
+        assert_eq!(it.next_back(), None);
+    }
+
+    #[test]
+    fn iter_ranges_test() {
+        let mut c = Utf8Charset::empty();
+        assert_eq!(Vec::from_iter(c.iter_ranges()), []);
+        assert!(!c.is_inverted());
+
+        c.add_range(('x', 'z'));
+        c.add_range(('0', '9'));
+        c.add_char('é');
+        assert_eq!(
+            Vec::from_iter(c.iter_ranges()),
+            [('0', '9'), ('x', 'z'), ('é', 'é')]
+        );
+
+        c.add_char('a');
+        c.add_range(('b', 'c'));
+        c.add_range(('y', '~'));
+        c.invert(true);
+        assert!(c.is_inverted());
+        assert_eq!(
+            Vec::from_iter(c.iter_ranges()),
+            [('0', '9'), ('a', 'c'), ('x', '~'), ('é', 'é')]
+        );
+    }
+
+    #[test]
+    fn from_iter_test() {
+        let c: Utf8Charset = vec![('a', 'z'), ('0', '9')].into_iter().collect();
+        assert!(!c.is_inverted());
+        for ch in ['a', 'q', 'z', '0', '9'] {
+            assert!(c.contains(ch));
+        }
+        for ch in ['A', '/', ':', '{', 'é'] {
+            assert!(!c.contains(ch));
+        }
+
+        let mut c = Utf8Charset::from_iter([('α', 'ω')]);
+        assert!(c.contains('β') && !c.contains('a'));
+        c.invert(true);
+        assert!(!c.contains('β') && c.contains('a'));
+        assert!(Utf8Charset::from_iter([]).iter_ranges().next().is_none());
+    }
+
+    #[test]
+    fn into_iter_test() {
+        let c = Charset::from_range((b'x', b'z')) | Charset::from_char(b'0');
+        let mut v = Vec::new();
+        for b in &c {
+            v.push(b);
+        }
+        assert_eq!(v, b"0xyz");
+        assert_eq!(Vec::from_iter(&c), Vec::from_iter(c.iter()));
+        assert_eq!((&Charset::full()).into_iter().count(), 256);
+        assert_eq!((&Charset::empty()).into_iter().next(), None);
+    }
+
+    #[test]
+    fn classes_test() {
+        for (class, f) in [
+            (Charset::digit(), u8::is_ascii_digit as fn(&u8) -> bool),
+            (Charset::word(), |c| c.is_ascii_alphanumeric() || *c == b'_'),
+            (Charset::whitespace(), |c| {
+                c.is_ascii_whitespace() || *c == 0x0b
+            }),
+        ] {
+            assert!((0..=255).all(|c| class.contains(c) == f(&c)));
+        }
+
+        assert!(Charset::non_digit().contains(0x80));
+        assert!(Charset::non_digit().contains(0xff));
+        assert!(!Charset::non_digit().contains(b'5'));
+        assert_eq!(Charset::non_digit().len(), 246);
+        assert!(Charset::non_word().contains(b'-') && Charset::non_word().contains(0x80));
+        assert!(!Charset::non_word().contains(b'_') && !Charset::non_word().contains(b'Z'));
+        assert!(
+            Charset::non_whitespace().contains(b'a') && Charset::non_whitespace().contains(0xa0)
+        );
+        assert!(
+            !Charset::non_whitespace().contains(b'\n') && !Charset::non_whitespace().contains(b' ')
+        );
+        assert_eq!(
+            Charset::non_whitespace() | Charset::whitespace(),
+            Charset::full()
+        );
+    }
+
+    #[test]
+    fn const_test() {
+        const LOWER: Charset = Charset::from_range_const(b'a', b'z');
+        const ALL: Charset = Charset::from_range_const(0, 255);
+        const NONE: Charset = Charset::EMPTY;
+        assert_eq!(LOWER.len(), 26);
+        assert!(LOWER.contains(b'a') && LOWER.contains(b'z'));
+        assert!(!LOWER.contains(b'`') && !LOWER.contains(b'{'));
+        assert_eq!(ALL, Charset::full());
+        assert_eq!(NONE, Charset::empty());
+        assert_eq!(Charset::from_range_const(b'z', b'a'), NONE);
+        for (lo, hi) in [(0, 0), (63, 64), (127, 128), (200, 255), (255, 255)] {
+            assert_eq!(
+                Vec::from_iter(Charset::from_range_const(lo, hi).iter()),
+                Vec::from_iter(lo..=hi)
+            );
+        }
+    }
+
+    #[test]
+    fn bitor_test() {
+        let c = Charset::empty() | b'a' | (b'0', b'9');
+        assert!(c.contains(b'a') && (b'0'..=b'9').all(|d| c.contains(d)));
+        assert_eq!(c.len(), 11);
+        assert_eq!(c, byteset![b'a' b'0'-b'9']);
+        assert_eq!(Charset::full() | 0, Charset::full());
+        assert_eq!(Charset::empty() | (5, 3), Charset::empty());
+    }
+
+    #[test]
+    fn from_ranges_test() {
+        assert_eq!(
+            Charset::from_ranges(&[(b'0', b'9'), (b'a', b'f')]),
+            Charset::from_range((b'0', b'9')) | Charset::from_range((b'a', b'f'))
+        );
+        assert_eq!(Charset::from_ranges(&[]), Charset::empty());
+        assert_eq!(Charset::from_ranges(&[(0, 255), (3, 4)]), Charset::full());
+        assert_eq!(Charset::from_ranges(&[(5, 3)]), Charset::empty());
+        const HEX: Charset = Charset::from_ranges(&[(b'0', b'9'), (b'a', b'f'), (b'A', b'F')]);
+        assert_eq!(HEX.len(), 22);
+    }
+
+    #[test]
+    fn for_each_test() {
+        let sets = [
+            Charset::empty(),
+            Charset::full(),
+            byteset![0 1 63 64 127 128 191 192 255],
+            Charset::from_range((b'a', b'z')) | Charset::from_range((0x70, 0xa0)),
+        ];
+        for c in sets {
+            let mut bytes = Vec::new();
+            c.for_each(|b| bytes.push(b));
+            assert_eq!(bytes, Vec::from_iter(c.iter()));
+        }
+    }
+
+    #[test]
+    fn extend_test() {
+        let mut c = Utf8Charset::from_iter([('a', 'c')]);
+        c.extend("xé😀".chars());
+        assert!(c.contains('b') && c.contains('x') && c.contains('é') && c.contains('😀'));
+        assert!(!c.contains('d') && !c.contains('y'));
+        c.extend([('0', '9'), ('α', 'ω')]);
+        assert!(c.contains('5') && c.contains('β') && !c.contains('A'));
+
+        let mut c = Utf8Charset::empty();
+        c.invert(true);
+        c.extend(['a', 'b']);
+        c.extend([('x', 'z')]);
+        assert!(c.is_inverted());
+        assert!(!c.contains('a') && !c.contains('y') && c.contains('c'));
+    }
+
+    #[test]
+    fn add_str_test() {
+        let mut c = Utf8Charset::empty();
+        c.add_str("abc");
+        assert!(c.contains('a') && c.contains('b') && c.contains('c'));
+        assert!(!c.contains('d') && !c.contains('A'));
+        c.add_str("ñ€😀-");
+        assert!(c.contains('ñ') && c.contains('€') && c.contains('😀') && c.contains('-'));
+        assert!(!c.contains('ò') && !c.contains(','));
+        assert_eq!(
+            c,
+            Utf8Charset::from_iter([('a', 'c'), ('-', '-'), ('ñ', 'ñ'), ('€', '€'), ('😀', '😀')])
+        );
+        c.add_str("");
+        c.invert(true);
+        assert!(!c.contains('€') && c.contains('d'));
+    }
+
+    #[test]
+    fn case_fold_test() {
+        let c =
+            Utf8Charset::from_iter([('a', 'c'), ('X', 'X'), ('0', '9'), ('é', 'é'), ('Σ', 'Σ')]);
+        let f = c.case_fold();
+        for ch in ['a', 'A', 'c', 'C', 'x', 'X', '5', 'é', 'É', 'σ', 'Σ'] {
+            assert!(f.contains(ch));
+        }
+        for ch in ['d', 'D', 'e', 'ς'] {
+            assert!(!f.contains(ch));
+        }
+        let mut c = Utf8Charset::from_iter([('a', 'a')]);
+        c.invert(true);
+        let f = c.case_fold();
+        assert!(f.is_inverted() && !f.contains('a') && !f.contains('A') && f.contains('b'));
+        let all = Utf8Charset::from_iter([('\0', char::MAX)]);
+        assert_eq!(all.case_fold(), all);
+
+        let c =
+            Charset::from_range((b'a', b'c')) | Charset::from_char(b'0') | Charset::from_char(0xe9);
+        let expected = c | Charset::from_range((b'A', b'C'));
+        assert_eq!(c.case_fold(), expected);
+        assert_eq!(Charset::full().case_fold(), Charset::full());
+    }
+
+    #[test]
+    fn utf8_intersect_test() {
+        let a = Utf8Charset::from_iter([('a', 'm')]);
+        let b = Utf8Charset::from_iter([('h', 'z')]);
+        let c = a.intersect(&b);
+        assert!(!c.is_inverted());
+        assert!(c.iter_ranges().eq([('h', 'm')]));
+        assert_eq!(c, b.intersect(&a));
+
+        // [^a-c] && [a-z0-9]
+        let mut a = Utf8Charset::from_iter([('a', 'c')]);
+        a.invert(true);
+        let b = Utf8Charset::from_iter([('a', 'z'), ('0', '9')]);
+        let c = a.intersect(&b);
+        assert!(!c.is_inverted());
+        assert!(c.iter_ranges().eq([('0', '9'), ('d', 'z')]));
+
+        // [^a] && [^b]
+        let mut b = Utf8Charset::from_iter([('b', 'b')]);
+        b.invert(true);
+        let mut expected = Utf8Charset::from_iter([('a', 'b')]);
+        expected.invert(true);
+        let mut a = Utf8Charset::from_iter([('a', 'a')]);
+        a.invert(true);
+        assert_eq!(a.intersect(&b), expected);
+        assert!(!a.intersect(&b).is_inverted());
+
+        let a = Utf8Charset::from_iter([('a', 'c'), ('x', 'z')]);
+        let b = Utf8Charset::from_iter([('d', 'w')]);
+        assert_eq!(a.intersect(&b), Utf8Charset::empty());
+    }
+
+    #[test]
+    fn utf8_eq_test() {
+        let a = Utf8Charset::from_iter([('a', 'c')]);
+        let mut b = Utf8Charset::empty();
+        b.add_char('c');
+        b.add_range(('a', 'b'));
+        b.add_range(('b', 'b'));
+        assert_eq!(a, b);
+        b.add_char('d');
+        assert_ne!(a, b);
+
+        let mut a = Utf8Charset::empty();
+        a.add_char('a');
+        a.invert(true);
+        let b = Utf8Charset::from_iter([('\0', '`'), ('b', char::MAX)]);
+        assert_eq!(a, b);
+        let b = Utf8Charset::from_iter([('\0', '`'), ('b', '\u{d7ff}'), ('\u{e000}', char::MAX)]);
+        assert_eq!(a, b);
+        assert_ne!(
+            a,
+            Utf8Charset::from_iter([('\0', '`'), ('b', '\u{10fffe}')])
+        );
+
+        let mut full = Utf8Charset::empty();
+        full.invert(true);
+        assert_eq!(full, Utf8Charset::from_iter([('\0', char::MAX)]));
+        assert_eq!(Utf8Charset::empty(), Utf8Charset::from_iter([('z', 'a')]));
+    }
+
+    #[test]
+    fn from_utf8_byte_class_test() {
+        let uc = Utf8Charset::from_iter([('a', 'c'), ('0', '0'), ('é', 'é')]);
+        let expected =
+            Charset::from_range((b'a', b'c')) | Charset::from_char(b'0') | Charset::from_char(0xe9);
+        assert_eq!(Charset::from_utf8_byte_class(&uc), Some(expected));
+        let mut uc = Utf8Charset::from_iter([('\0', '\u{7f}')]);
+        uc.invert(true);
+        assert_eq!(
+            Charset::from_utf8_byte_class(&uc),
+            Some(Charset::from_range((0x80, 0xff)))
+        );
+        assert_eq!(
+            Charset::from_utf8_byte_class(&Utf8Charset::empty()),
+            Some(Charset::empty())
+        );
+        let uc = Utf8Charset::from_iter([('a', 'z'), ('α', 'ω')]);
+        assert_eq!(Charset::from_utf8_byte_class(&uc), None);
+        let uc = Utf8Charset::from_iter([('a', '\u{100}')]);
+        assert_eq!(Charset::from_utf8_byte_class(&uc), None);
+    }
+
+    #[test]
+    fn invert_within_test() {
+        let vowels = byteset![b'a' b'e' b'i' b'o' b'u' b'A'];
+        let consonants = vowels.invert_within(b'a', b'z');
+        assert_eq!(consonants.len(), 21);
+        assert!(consonants.contains(b'b') && consonants.contains(b'z'));
+        assert!(!consonants.contains(b'a') && !consonants.contains(b'u'));
+        assert!(!consonants.contains(b'A') && !consonants.contains(b'0'));
+        assert_eq!(
+            Charset::empty().invert_within(3, 7),
+            Charset::from_range((3, 7))
+        );
+        assert_eq!(Charset::full().invert_within(0, 255), Charset::empty());
+        assert_eq!(vowels.invert_within(0, 255), vowels.complement());
+    }
+
+    #[test]
+    fn try_from_test() {
+        assert_eq!(Charset::try_from('a'), Ok(Charset::from_char(b'a')));
+        assert_eq!(Charset::try_from('\0'), Ok(Charset::from_char(0)));
+        assert_eq!(Charset::try_from('\u{ff}'), Ok(Charset::from_char(0xff)));
+        assert!(Charset::try_from('€').is_err());
+        assert!(Charset::try_from('\u{100}').is_err());
+    }
+
+    #[test]
+    fn intersects_test() {
+        let range = |a, b| Charset::from_range((a, b));
+        // overlapping
+        assert!(range(b'a', b'm').intersects(&range(b'h', b'z')));
+        assert!(range(0, 255).intersects(&Charset::from_char(200)));
+        assert!(range(60, 70).intersects(&range(70, 130)));
+        // touching, but disjoint
+        assert!(!range(b'a', b'm').intersects(&range(b'n', b'z')));
+        assert!(!range(0, 63).intersects(&range(64, 127)));
+        // fully disjoint
+        assert!(!Charset::digit().intersects(&Charset::whitespace()));
+        assert!(!Charset::empty().intersects(&Charset::full()));
+        for (a, b) in [
+            (range(1, 100), range(90, 200)),
+            (range(1, 10), range(20, 30)),
+        ] {
+            assert_eq!(a.intersects(&b), !(a & b).is_empty());
+            assert_eq!(a.intersects(&b), b.intersects(&a));
+        }
+    }
+
+    #[test]
+    fn disjoint_partition_test() {
+        let range = |a, b| Charset::from_range((a, b));
+        let parts = Charset::disjoint_partition(&[range(b'a', b'z'), range(b'm', b'p')]);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.contains(&range(b'm', b'p')));
+        // `[a-l]` and `[q-z]` belong to the same sets, so they form a single part
+        assert!(parts.contains(&(range(b'a', b'l') | range(b'q', b'z'))));
+
+        let sets = [
+            range(b'a', b'f'),
+            range(b'd', b'k'),
+            Charset::empty(),
+            range(b'x', b'z'),
+        ];
+        let parts = Charset::disjoint_partition(&sets);
+        assert_eq!(parts.len(), 4);
+        for (i, p) in parts.iter().enumerate() {
+            assert!(!p.is_empty());
+            assert!(parts[i + 1..].iter().all(|q| (*p & *q).is_empty()));
+        }
+        for s in sets {
+            let union = parts
+                .iter()
+                .filter(|p| (**p & s) == **p)
+                .fold(Charset::empty(), |a, p| a | *p);
+            assert_eq!(union, s);
+        }
+        assert!(Charset::disjoint_partition(&[]).is_empty());
+    }
+
+    #[test]
+    fn display_as_test() {
+        let c = Charset::from_range((b'a', b'z'))
+            | Charset::from_range((b'0', b'1'))
+            | byteset![b'-' b'\\' b'\'' b'\n' 0xff];
+        assert_eq!(
+            c.display_as(DisplayStyle::Regex),
+            "[\\x0a\\'\\-01\\\\a-z\\xff]"
+        );
+        assert_eq!(
+            c.display_as(DisplayStyle::RustByteLiteral),
+            "b'\\x0a' | b'\\'' | b'-' | b'0'..=b'1' | b'\\\\' | b'a'..=b'z' | b'\\xff'"
+        );
+        assert_eq!(
+            Charset::from_range((b'0', b'2')).display_as(DisplayStyle::Verbose),
+            "\\x30\\x31\\x32"
+        );
+        assert_eq!(Charset::empty().display_as(DisplayStyle::Regex), "[]");
+        assert_eq!(
+            Charset::empty().display_as(DisplayStyle::RustByteLiteral),
+            ""
+        );
+        assert_eq!(
+            Charset::full().display_as(DisplayStyle::Regex),
+            "[\\x00-\\xff]"
+        );
+
+        for c in [
+            c,
+            !c,
+            Charset::empty(),
+            Charset::full(),
+            byteset![b'^' b'[' b']'],
+        ] {
+            let s = c.display_as(DisplayStyle::Regex);
+            assert_eq!(s[1..s.len() - 1].parse::<Charset>(), Ok(c));
+        }
+    }
+
+    #[test]
+    fn hex_test() {
+        for c in [
+            Charset::empty(),
+            Charset::full(),
+            Charset::from_char(0),
+            Charset::from_char(255),
+            Charset::word(),
+            Charset::from_range((60, 200)),
+        ] {
+            let hex = c.to_hex();
+            assert_eq!(hex.len(), 64);
+            assert_eq!(Charset::from_hex(&hex), Ok(c));
+            assert_eq!(Charset::from_hex(&hex.to_uppercase()), Ok(c));
+        }
+        assert_eq!(Charset::empty().to_hex(), "0".repeat(64));
+        assert_eq!(Charset::full().to_hex(), "f".repeat(64));
+        assert_eq!(
+            Charset::from_char(0).to_hex(),
+            format!("{}1", "0".repeat(63))
+        );
+        assert_eq!(
+            Charset::from_char(255).to_hex(),
+            format!("8{}", "0".repeat(63))
+        );
+        assert_eq!(Charset::from_hex(""), Err(FromHexError));
+        assert_eq!(Charset::from_hex(&"0".repeat(63)), Err(FromHexError));
+        assert_eq!(
+            Charset::from_hex(&format!("+{}", "0".repeat(63))),
+            Err(FromHexError)
+        );
+        assert_eq!(
+            Charset::from_hex(&format!("g{}", "0".repeat(63))),
+            Err(FromHexError)
+        );
+    }
+
+    #[test]
+    fn len_test() {
+        for c in [
+            Charset::empty(),
+            Charset::full(),
+            Charset::from_char(0),
+            Charset::from_char(255),
+            Charset::from_range((60, 200)),
+            Charset::from_range((64, 127)) | Charset::from_range((190, 193)),
+            Charset::word(),
+            Charset::non_whitespace(),
+        ] {
+            assert_eq!(c.len(), c.iter().count());
+            assert_eq!(c.first(), c.iter().next());
+            assert_eq!(c.last(), c.iter().last());
+        }
+    }
+
+    #[test]
+    fn default_test() {
+        assert_eq!(Charset::default(), Charset::empty());
+        let c = Utf8Charset::default();
+        assert!(c.ranges.is_empty() && !c.invert);
+    }
+
+    #[test]
+    fn byteset_test() {
+        assert_eq!(
+            byteset![b'a'-b'z' b'_'],
+            Charset::from_range((b'a', b'z')) | Charset::from_char(b'_')
+        );
+        assert_eq!(byteset![], Charset::empty());
+        assert_eq!(byteset![0x80-0xff 0 b'x'-b'x'], {
+            Charset::from_range((0x80, 0xff)) | Charset::from_char(0) | Charset::from_char(b'x')
+        });
+        let c = byteset![^ b'0'-b'9' b'\n'];
+        assert!(!c.contains(b'5') && !c.contains(b'\n'));
+        assert!(c.contains(b'a') && c.contains(0xff));
+    }
+
+    #[test]
+    fn contains_range_test() {
+        let c = Charset::from_range((b'a', b'z')) | Charset::from_char(b'_');
+        assert!(c.contains_range((b'a', b'z')));
+        assert!(c.contains_range((b'c', b'f')));
+        assert!(c.contains_range((b'_', b'_')));
+        assert!(!c.contains_range((b'_', b'a')));
+        assert!(!c.contains_range((b'x', b'~')));
+        assert!(!c.contains_range((b'0', b'9')));
+        assert!(Charset::from_range((0, 255)).contains_range((0, 255)));
+        assert!(!Charset::empty().contains_range((0, 0)));
+    }
+
+    #[test]
+    fn first_last_test() {
+        assert_eq!(Charset::empty().first(), None);
+        assert_eq!(Charset::empty().last(), None);
+        for c in [0, 1, b'a', 0x80, 255] {
+            assert_eq!(Charset::from_char(c).first(), Some(c));
+            assert_eq!(Charset::from_char(c).last(), Some(c));
+        }
+        assert_eq!(Charset::from_range((0, 255)).first(), Some(0));
+        assert_eq!(Charset::from_range((0, 255)).last(), Some(255));
+        for c in [
+            Charset::from_range((b'a', b'z')) | Charset::from_char(b'0'),
+            Charset::from_range((0x70, 0x90)) | Charset::from_char(0xfe),
+            Charset::from_char(3) | Charset::from_char(200),
+        ] {
+            assert_eq!(c.first(), c.iter().next());
+            assert_eq!(c.last(), c.iter().last());
+        }
+    }
+
     #[test]
     fn char_ranges_test() {
         // intersection
@@ -312,5 +1473,194 @@ mod charset_test {
             *subtract_ranges(&[('\u{0}', '\u{10ffff}')], &[('\u{1}', '\u{10ffff}')]),
             [('\u{0}', '\u{0}')]
         );
+
+        // ranges around surrogates
+        assert_eq!(
+            *subtract_ranges(&[('\0', char::MAX)], &[('\u{e000}', '\u{e000}')]),
+            [('\0', '\u{d7ff}'), ('\u{e001}', char::MAX)]
+        );
+        assert_eq!(
+            *subtract_ranges(&[('\0', char::MAX)], &[('\u{d7ff}', '\u{d7ff}')]),
+            [('\0', '\u{d7fe}'), ('\u{e000}', char::MAX)]
+        );
+        assert_eq!(
+            *subtract_ranges(&[('\u{d000}', '\u{e500}')], &[('\0', '\u{d7ff}')]),
+            [('\u{e000}', '\u{e500}')]
+        );
+        assert_eq!(
+            *subtract_ranges(&[('\u{d000}', '\u{e500}')], &[('\u{e000}', char::MAX)]),
+            [('\u{d000}', '\u{d7ff}')]
+        );
+        assert_eq!(prev_char('\u{e000}'), Some('\u{d7ff}'));
+        assert_eq!(next_char('\u{d7ff}'), Some('\u{e000}'));
+        assert_eq!(prev_char('\0'), None);
+        assert_eq!(next_char(char::MAX), None);
+        assert_eq!(next_char('a'), Some('b'));
+
+        // overlapping and unsorted ranges
+        assert_eq!(
+            *subtract_ranges(&[('a', 'z')], &[('c', 'f'), ('d', 'h')]),
+            [('a', 'b'), ('i', 'z')]
+        );
+        assert_eq!(
+            *subtract_ranges(&[('a', 'z')], &[('d', 'h'), ('c', 'f')]),
+            [('a', 'b'), ('i', 'z')]
+        );
+        assert_eq!(
+            *subtract_ranges(&[('m', 'z'), ('a', 'n')], &[('x', 'x')]),
+            [('a', 'w'), ('y', 'z')]
+        );
+        assert_eq!(
+            normalize_ranges(&[('x', 'z'), ('a', 'c'), ('d', 'f'), ('q', 'p'), ('b', 'e')]),
+            [('a', 'f'), ('x', 'z')]
+        );
+    }
+
+    /// Creates UTnfa from character range
+    fn multibyte_range(a: char, b: char) -> UTnfa {
+        let mut seqs = Vec::new();
+        multibyte_sequences(a, b, &mut seqs);
+        sequences_utnfa(&seqs)
+    }
+
+    /// Creates UTnfa, that matches any of `seqs`, without sharing states
+    fn naive_sequences_utnfa(seqs: &[Vec<(u8, u8)>]) -> UTnfa {
+        UTnfa::union_all(seqs.iter().map(|seq| {
+            UTnfa::concat_all(seq.iter().map(|r| UTnfa::charset(Charset::from_range(*r))))
+        }))
+    }
+
+    #[test]
+    fn shared_suffixes_test() {
+        for ranges in [
+            &[('\0', char::MAX)][..],
+            &[('a', 'z'), ('é', '€')],
+            &[('\u{ff}', '\u{100}'), ('\u{d000}', '\u{e500}')],
+            &[('\u{3a5}', '\u{10abc}'), ('\u{fffe}', '\u{10000}')],
+            &[],
+        ] {
+            let mut seqs = Vec::new();
+            for r in ranges {
+                multibyte_sequences(r.0, r.1, &mut seqs);
+            }
+            let a = sequences_utnfa(&seqs);
+            let naive = naive_sequences_utnfa(&seqs);
+            assert!(crate::equivalent(&a, &naive));
+            assert!(a.nodes() <= naive.nodes());
+        }
+
+        // initial and final states, and states for suffixes `[80-bf]{1,3}`, `[a0-bf][80-bf]`,
+        // `[80-9f][80-bf]`, `[90-bf][80-bf]{2}` and `[80-8f][80-bf]{2}`
+        let a = UTnfa::any_char();
+        assert_eq!(a.nodes(), 9);
+        assert_eq!(a.list_range_edges().count(), 15);
+    }
+
+    #[test]
+    fn utnfa_from_ranges_test() {
+        let ranges = [('α', 'ω'), ('a', 'f'), ('c', 'k'), ('😀', '😀'), ('z', 'x')];
+        for invert in [false, true] {
+            let mut c = Utf8Charset::from_iter(ranges);
+            c.invert(invert);
+            let a = utnfa_from_ranges(ranges.iter().copied(), invert);
+            assert!(crate::equivalent(&a, &UTnfa::from(c.clone())));
+            for ch in ['a', 'k', 'l', 'β', 'A', '😀', '😁'] {
+                let mut buf = [0; 4];
+                assert_eq!(
+                    a.accepts(ch.encode_utf8(&mut buf).as_bytes()),
+                    c.contains(ch)
+                );
+            }
+        }
+        assert!(utnfa_from_ranges([], false).is_empty_language());
+        assert!(crate::equivalent(
+            &utnfa_from_ranges([], true),
+            &UTnfa::any_char()
+        ));
+    }
+
+    #[test]
+    fn multibyte_range_test() {
+        let mut buf = [0; 4];
+        for c in [
+            '\0', 'a', '\u{7f}', '\u{80}', 'é', '\u{7ff}', '\u{800}', '€', '\u{ffff}',
+        ] {
+            assert_eq!(
+                encode_utf8(c as u32, c.len_utf8())[..c.len_utf8()],
+                *c.encode_utf8(&mut buf).as_bytes()
+            );
+        }
+        for c in ['\u{10000}', '😀', '\u{10ffff}'] {
+            assert_eq!(
+                encode_utf8(c as u32, 4),
+                *c.encode_utf8(&mut buf).as_bytes()
+            );
+        }
+
+        // ranges, that span different leading bytes
+        let a = multibyte_range('\u{ff}', '\u{100}');
+        assert!(a.accepts("\u{ff}".as_bytes()));
+        assert!(a.accepts("\u{100}".as_bytes()));
+        assert!(!a.accepts("\u{fe}".as_bytes()));
+        assert!(!a.accepts("\u{101}".as_bytes()));
+        assert!(!a.accepts("\u{13f}".as_bytes()));
+        assert!(!a.accepts("\u{c0}".as_bytes()));
+
+        // surrogates are skipped
+        let a = multibyte_range('\u{d000}', '\u{e500}');
+        for c in [
+            '\u{d000}', '\u{d7ff}', '\u{e000}', '\u{e500}', '\u{d123}', '\u{e0ff}',
+        ] {
+            assert!(a.accepts(c.encode_utf8(&mut buf).as_bytes()));
+        }
+        for c in ['\u{cfff}', '\u{e501}', 'a', '😀'] {
+            assert!(!a.accepts(c.encode_utf8(&mut buf).as_bytes()));
+        }
+        for s in [b"\xed\xa0\x80", b"\xed\xbf\xbf", b"\xed\xb0\x80"] {
+            assert!(!a.accepts(s));
+        }
+        let strings = a.matches_up_to(3);
+        assert_eq!(strings.len(), 0xe501 - 0xd000 - 0x800);
+        assert!(strings.iter().all(|s| str::from_utf8(s).is_ok()));
+
+        let a = multibyte_range('a', '\u{10ffff}');
+        for c in ['a', 'z', '\u{80}', 'é', '€', '\u{ffff}', '😀', '\u{10ffff}'] {
+            assert!(a.accepts(c.encode_utf8(&mut buf).as_bytes()));
+        }
+        for s in [
+            &b"\0"[..],
+            b"`",
+            b"\xc0\x80",
+            b"\xed\xa0\x80",
+            b"\xe0\x80\x80",
+            b"\xf0\x80\x80\x80",
+            b"\xf4\x90\x80\x80",
+        ] {
+            assert!(!a.accepts(s));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_test() {
+        for c in [
+            Charset::empty(),
+            Charset::from_char(0),
+            Charset::from_char(255),
+            Charset::from_range((b'a', b'z')) | Charset::from_range((0x80, 0xc0)),
+        ] {
+            let json = serde_json::to_string(&c).unwrap();
+            assert_eq!(serde_json::from_str::<Charset>(&json).unwrap(), c);
+        }
+        assert!(serde_json::from_str::<Charset>("[1, 2, 3]").is_err());
+
+        let mut c = Utf8Charset::empty();
+        c.add_range(('a', 'z'));
+        c.add_char('é');
+        c.invert(true);
+        let json = serde_json::to_string(&c).unwrap();
+        let d: Utf8Charset = serde_json::from_str(&json).unwrap();
+        assert_eq!(d.ranges, c.ranges);
+        assert_eq!(d.invert, c.invert);
     }
 }
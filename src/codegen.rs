@@ -0,0 +1,152 @@
+//! Generation of Rust source code from automata
+
+use itertools::Itertools;
+
+use crate::collections::BTreeMap;
+use crate::prelude::*;
+use crate::{Automata, Charset, DisplayStyle};
+
+/// Generates Rust source code of a table-driven matcher for deterministic automata `dfa`.
+/// The generated code contains:
+/// - `NAME_TABLE: [[u16; 256]; N]`, where `NAME_TABLE[s][c]` is the state, reached from `s`
+///   by char `c`, or `u16::MAX` if there is no such transition
+/// - `NAME_FINALS: [bool; N]`, where `NAME_FINALS[s]` is `true` if `s` is final
+/// - `fn name_step(state: usize, c: u8) -> Option<usize>`, that gives the same transitions
+///   as `NAME_TABLE`, but as a `match` over byte patterns, see `DisplayStyle::RustByteLiteral`
+/// - `fn name_run(input: &[u8]) -> Option<usize>`, that returns length of the longest prefix
+///   of `input`, accepted by `dfa`
+///
+/// Here `NAME` and `name` are `name` in upper and lower case respectively.
+/// Panics if `dfa` is not deterministic or has too many states,
+/// or if `name` is not an identifier, i.e. doesn't match `[A-Za-z_][A-Za-z0-9_]*`
+pub fn emit_rust(dfa: &impl Automata, name: &str) -> String {
+    let mut chars = name.chars();
+    assert!(
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        "emit_rust() requires name to be an identifier, got {name:?}"
+    );
+    assert!(
+        dfa.is_deterministic(),
+        "emit_rust() requires deterministic automata"
+    );
+    assert!(dfa.nodes() < u16::MAX as usize, "too many states");
+    let (table, finals) = dfa.transition_table();
+
+    let (upper, lower) = (name.to_uppercase(), name.to_lowercase());
+    let n = dfa.nodes();
+    let mut s = String::from("// Generated by rcclex, do not edit\n\n");
+    s += &format!("pub const {upper}_TABLE: [[u16; 256]; {n}] = [\n");
+    for row in table.iter() {
+        s += "    [\n";
+        for chunk in row.chunks(16) {
+            let chunk = chunk
+                .iter()
+                .map(|b| b.map_or(u16::MAX, |b| b as u16))
+                .join(", ");
+            s += &format!("        {chunk},\n");
+        }
+        s += "    ],\n";
+    }
+    s += "];\n\n";
+    let finals = finals.iter().join(", ");
+    s += &format!("pub const {upper}_FINALS: [bool; {n}] = [{finals}];\n\n");
+
+    let mut labels: BTreeMap<(usize, usize), Charset> = BTreeMap::new();
+    for (a, b, c, _) in dfa.list_range_edges() {
+        *labels.entry((a, b)).or_default() |=
+            c.expect("deterministic automata has no epsilon edges");
+    }
+    s += "/// Returns the state, reached from `state` by char `c`, if any\n";
+    s += &format!("pub fn {lower}_step(state: usize, c: u8) -> Option<usize> {{\n");
+    s += "    match (state, c) {\n";
+    for ((a, b), c) in labels.iter() {
+        let pattern = c.display_as(DisplayStyle::RustByteLiteral);
+        s += &format!("        ({a}, {pattern}) => Some({b}),\n");
+    }
+    s += "        _ => None,\n    }\n}\n\n";
+    s + &format!(
+        "\
+/// Returns length of the longest prefix of `input`, accepted by the automata
+pub fn {lower}_run(input: &[u8]) -> Option<usize> {{
+    let mut state = {begin};
+    let mut last = if {upper}_FINALS[state] {{ Some(0) }} else {{ None }};
+    for (i, c) in input.iter().enumerate() {{
+        match {upper}_TABLE[state][*c as usize] {{
+            u16::MAX => break,
+            next => state = next as usize,
+        }}
+        if {upper}_FINALS[state] {{
+            last = Some(i + 1);
+        }}
+    }}
+    last
+}}
+",
+        begin = dfa.begin(),
+    )
+}
+
+#[cfg(test)]
+mod codegen_test {
+    use super::*;
+    use crate::SimpleAutomata;
+    use crate::collections::HashSet;
+
+    #[test]
+    fn emit_rust_test() {
+        // ab*
+        let dfa = SimpleAutomata {
+            begin: 0,
+            nodes: 2,
+            finals: HashSet::from([1]),
+            edges: vec![(0, 1, Some(b'a'), -1), (1, 1, Some(b'b'), -1)],
+        };
+        let code = emit_rust(&dfa, "Ab");
+        assert!(code.contains("pub const AB_TABLE: [[u16; 256]; 2] = ["));
+        assert!(code.contains("pub const AB_FINALS: [bool; 2] = [false, true];"));
+        assert!(code.contains("pub fn ab_run(input: &[u8]) -> Option<usize> {"));
+        assert!(code.contains("let mut state = 0;"));
+        assert!(code.contains("pub fn ab_step(state: usize, c: u8) -> Option<usize> {"));
+        assert!(code.contains("        (0, b'a') => Some(1),\n        (1, b'b') => Some(1),\n"));
+
+        let table = &code[code.find("AB_TABLE").unwrap()..code.find("AB_FINALS").unwrap()];
+        assert_eq!(table.matches("    [\n").count(), 2);
+        let entries = Vec::from_iter(
+            table[table.find('=').unwrap()..]
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|s| !s.is_empty()),
+        );
+        assert_eq!(entries.len(), 512);
+        assert_eq!(entries[b'a' as usize], "1");
+        assert_eq!(entries[256 + b'b' as usize], "1");
+        assert_eq!(entries.iter().filter(|e| **e == "65535").count(), 510);
+        assert_eq!(code.matches('[').count(), code.matches(']').count());
+        assert_eq!(code.matches('{').count(), code.matches('}').count());
+
+        assert!(emit_rust(&dfa, "_my_lexer2").contains("pub fn _my_lexer2_run("));
+    }
+
+    fn empty() -> SimpleAutomata {
+        SimpleAutomata {
+            begin: 0,
+            nodes: 1,
+            finals: HashSet::new(),
+            edges: vec![],
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn emit_rust_name_test() {
+        emit_rust(&empty(), "my-lexer");
+    }
+
+    #[test]
+    #[should_panic]
+    fn emit_rust_name_digit_test() {
+        emit_rust(&empty(), "1x");
+    }
+}
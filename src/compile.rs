@@ -0,0 +1,166 @@
+use core::fmt::Display;
+
+use crate::{
+    Anchor, Automata, Captures, DeterminizeError, DeterminizeOptions, DfaMatcher, Matcher,
+    ParseError, Tdfa, Tnfa, parse_with_names,
+};
+
+/// Limit of DFA states, used by `compile`, so that patterns, which blow up exponentially,
+/// are rejected instead of exhausting memory
+pub const COMPILE_MAX_STATES: usize = 10_000;
+
+/// Regex, compiled by `compile`.
+/// Acceptance is checked by DfaMatcher, so each byte of input is processed in O(1),
+/// and submatches are extracted by Matcher, which only runs on input, accepted by the DFA
+#[derive(Clone, Debug)]
+pub struct CompiledRegex {
+    dfa: DfaMatcher,
+    matcher: Matcher,
+}
+
+/// Error, that occured while compiling a regex by `compile`
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompileError {
+    /// Pattern is not a valid regex
+    Parse(ParseError),
+    /// DFA exceeds the given number of states, see `COMPILE_MAX_STATES`
+    TooManyStates(usize),
+    /// Pattern contains anchor, which can't be evaluated by DfaMatcher
+    UnsupportedAnchor(Anchor),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CompileError::Parse(e) => write!(f, "{e}"),
+            CompileError::TooManyStates(n) => write!(f, "automata exceeds {n} states"),
+            CompileError::UnsupportedAnchor(a) => write!(f, "anchor {a:?} is not supported"),
+        }
+    }
+}
+
+impl core::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            CompileError::Parse(e) => Some(e),
+            CompileError::TooManyStates(_) | CompileError::UnsupportedAnchor(_) => None,
+        }
+    }
+}
+
+impl From<DeterminizeError> for CompileError {
+    fn from(e: DeterminizeError) -> Self {
+        CompileError::TooManyStates(e.max_states)
+    }
+}
+
+impl From<ParseError> for CompileError {
+    fn from(e: ParseError) -> Self {
+        CompileError::Parse(e)
+    }
+}
+
+/// Compiles regex `pattern` into a ready-to-run CompiledRegex.
+/// The pattern is parsed by `parse_with_names`, converted to Tnfa, determinized and minimized.
+/// If determinized automata exceeds `COMPILE_MAX_STATES` states, `TooManyStates` is returned.
+/// Anchors `^`, `$` and `\b` are rejected with `UnsupportedAnchor`, as DfaMatcher has no way
+/// to evaluate them.
+/// Tags are dropped after determinization, capturing groups are extracted by `Matcher` over the
+/// parsed pattern, see `CompiledRegex::captures`
+pub fn compile(pattern: &str) -> Result<CompiledRegex, CompileError> {
+    let options = DeterminizeOptions {
+        max_states: Some(COMPILE_MAX_STATES),
+    };
+    let (nfa, names) = parse_with_names(pattern)?;
+    if let Some(a) = nfa
+        .list_edges()
+        .find_map(|(_, _, _, t)| Anchor::from_tag(t))
+    {
+        return Err(CompileError::UnsupportedAnchor(a));
+    }
+    let dfa = Tdfa::determinize_with(&Tnfa::from(&nfa), &options)?;
+    Ok(CompiledRegex {
+        dfa: DfaMatcher::new(&dfa.to_untagged().minimize()),
+        matcher: Matcher::new(&nfa).with_names(names),
+    })
+}
+
+impl CompiledRegex {
+    /// Returns `true` if the whole `input` is accepted
+    pub fn matches(&self, input: &[u8]) -> bool {
+        self.dfa.matches(input)
+    }
+
+    /// Returns length of the longest prefix of `input`, that is accepted
+    pub fn longest_prefix(&self, input: &[u8]) -> Option<usize> {
+        self.dfa.longest_prefix(input)
+    }
+
+    /// Matches the whole `input`, returning its capturing groups, see `Matcher::captures`
+    pub fn captures<'a>(&'a self, input: &'a [u8]) -> Option<Captures<'a>> {
+        if !self.dfa.matches(input) {
+            return None;
+        }
+        self.matcher.captures(input)
+    }
+}
+
+#[cfg(test)]
+mod compile_test {
+    use super::*;
+    use crate::ParseErrorKind;
+
+    #[test]
+    fn compile_test() {
+        let m = compile("(a|b)*abb").unwrap();
+        for (s, ok) in [
+            ("abb", true),
+            ("babaabb", true),
+            ("ab", false),
+            ("abba", false),
+        ] {
+            assert_eq!(m.matches(s.as_bytes()), ok);
+        }
+        assert_eq!(m.longest_prefix(b"abbabbx"), Some(6));
+
+        let m = compile("(?P<key>[a-z]+)=([0-9]*)").unwrap();
+        let c = m.captures(b"abc=12").unwrap();
+        assert_eq!(c.get(0), Some(&b"abc=12"[..]));
+        assert_eq!(c.name("key"), Some(&b"abc"[..]));
+        assert_eq!(c.get(2), Some(&b"12"[..]));
+        assert_eq!(m.captures(b"abc=").unwrap().get(2), Some(&b""[..]));
+        assert!(m.captures(b"=12").is_none());
+
+        let error = compile("a(b").unwrap_err();
+        assert!(
+            matches!(&error, CompileError::Parse(e) if e.kind == ParseErrorKind::UnbalancedParen)
+        );
+        assert!(
+            error
+                .to_string()
+                .starts_with("unbalanced parenthesis at position 1")
+        );
+        assert!(core::error::Error::source(&error).is_some());
+
+        // needs 2^21 states
+        let error = compile("(a|b)*a(a|b){20}").unwrap_err();
+        assert_eq!(error, CompileError::TooManyStates(COMPILE_MAX_STATES));
+        assert_eq!(error.to_string(), "automata exceeds 10000 states");
+        assert!(core::error::Error::source(&error).is_none());
+        assert!(compile("(a|b)*a(a|b){4}").is_ok());
+
+        let error = compile("a\\bb").unwrap_err();
+        assert_eq!(error, CompileError::UnsupportedAnchor(Anchor::WordBoundary));
+        assert_eq!(error.to_string(), "anchor WordBoundary is not supported");
+        assert!(core::error::Error::source(&error).is_none());
+        assert_eq!(
+            compile("(a|^b)c").unwrap_err(),
+            CompileError::UnsupportedAnchor(Anchor::Start)
+        );
+        assert!(matches!(
+            compile("a$"),
+            Err(CompileError::UnsupportedAnchor(Anchor::End))
+        ));
+        assert!(compile("a\\$").is_ok());
+    }
+}
@@ -0,0 +1,269 @@
+use core::fmt::Display;
+
+use crate::prelude::*;
+use crate::{Automata, Tdfa, Tnfa, UTnfa};
+
+/// Builder of a multi-rule Lexer
+#[derive(Clone, Debug, Default)]
+pub struct LexerBuilder {
+    /// Rules with their token ids and priorities
+    rules: Vec<(UTnfa, usize, isize)>,
+    tie_break: TieBreak,
+}
+
+/// Policy of choosing a rule, when several rules match the same longest span
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Rule, that was added first, wins
+    #[default]
+    FirstRule,
+    /// Rule, that was added last, wins
+    LastRule,
+    /// Rule with the highest priority, given by `LexerBuilder::add_rule_with_priority`, wins.
+    /// Rules, added by `add_rule`, have priority 0, ties are broken as by `FirstRule`
+    Priority,
+}
+
+/// Lexer, that splits input into tokens, using a DFA built from several rules.
+/// Each final state of the DFA is mapped to a single rule among the rules, accepting in this state,
+/// which is chosen according to `TieBreak` policy of the builder
+#[derive(Clone, Debug)]
+pub struct Lexer {
+    begin: usize,
+    delta: Vec<[Option<usize>; 256]>,
+    accept: Vec<Option<usize>>,
+}
+
+/// Error, returned by `Lexer::tokenize`, if no rule matches at some position of input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LexError {
+    /// Byte offset of input, at which no rule matches a non-empty string
+    pub pos: usize,
+    /// Tokens, found before `pos`
+    pub tokens: Vec<(usize, usize, usize)>,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "no rule matches at position {}", self.pos)
+    }
+}
+
+impl core::error::Error for LexError {}
+
+impl LexerBuilder {
+    /// Adds rule, that matches `pattern` and produces tokens with id `token_id`
+    pub fn add_rule(&mut self, pattern: UTnfa, token_id: usize) -> &mut Self {
+        self.add_rule_with_priority(pattern, token_id, 0)
+    }
+
+    /// Adds rule, that matches `pattern` and produces tokens with id `token_id`.
+    /// `priority` is only used with `TieBreak::Priority`
+    pub fn add_rule_with_priority(
+        &mut self,
+        pattern: UTnfa,
+        token_id: usize,
+        priority: isize,
+    ) -> &mut Self {
+        self.rules.push((pattern, token_id, priority));
+        self
+    }
+
+    /// Sets policy of choosing a rule, when several rules match the same longest span.
+    /// Default is `TieBreak::FirstRule`
+    pub fn tie_break(&mut self, tie_break: TieBreak) -> &mut Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Builds Lexer from all rules.
+    /// Rules are united, and end of each rule is marked with a tag, unique to this rule.
+    /// After determinization, a tag is crossed on the way to a state iff the rule accepts in it
+    pub fn build(&self) -> Lexer {
+        let base = self
+            .rules
+            .iter()
+            .flat_map(|(r, _, _)| r.list_edges().map(|e| e.3))
+            .max()
+            .unwrap_or(-1)
+            + 1;
+        let nfa = UTnfa::union_all(
+            self.rules
+                .iter()
+                .enumerate()
+                .map(|(i, (rule, _, _))| rule.clone() + UTnfa::tag(base + i as isize)),
+        );
+        let dfa = Tdfa::determinize(&Tnfa::from(&nfa));
+
+        // rule `i` wins over rules with greater keys
+        let key = |i: usize| match self.tie_break {
+            TieBreak::FirstRule => (0, i as isize),
+            TieBreak::LastRule => (0, -(i as isize)),
+            TieBreak::Priority => (-self.rules[i].2, i as isize),
+        };
+        // the winning rule among rules, which tags are set by operation `op`
+        let rule = |op| {
+            dfa.tag_op(op)
+                .iter()
+                .filter(|t| **t >= base)
+                .map(|t| (t - base) as usize)
+                .min_by_key(|i| key(*i))
+                .map(|i| self.rules[i].1)
+        };
+        let mut lexer = Lexer {
+            begin: dfa.begin(),
+            delta: vec![[None; 256]; dfa.nodes()],
+            accept: vec![None; dfa.nodes()],
+        };
+        lexer.accept[dfa.begin()] = rule(dfa.begin_op());
+        for (a, b, c, op) in dfa.list_edges() {
+            let c = c.expect("Tdfa has no epsilon edges");
+            lexer.delta[a][c as usize] = Some(b);
+            lexer.accept[b] = rule(op);
+        }
+        lexer
+    }
+}
+
+impl Lexer {
+    /// Creates an empty LexerBuilder
+    pub fn builder() -> LexerBuilder {
+        LexerBuilder::default()
+    }
+
+    /// Splits `input` into tokens `(token_id, start, end)` using maximal munch:
+    /// at each position the longest non-empty match is taken.
+    /// If no rule matches at some position, `LexError` with this position
+    /// and all tokens before it is returned
+    pub fn tokenize(&self, input: &[u8]) -> Result<Vec<(usize, usize, usize)>, LexError> {
+        let mut res = Vec::new();
+        let mut pos = 0;
+        while pos < input.len() {
+            let mut state = self.begin;
+            let mut last = None;
+            for (i, c) in input.iter().enumerate().skip(pos) {
+                match self.delta[state][*c as usize] {
+                    None => break,
+                    Some(next) => state = next,
+                }
+                if let Some(token) = self.accept[state] {
+                    last = Some((token, i + 1));
+                }
+            }
+            let Some((token, end)) = last else {
+                return Err(LexError { pos, tokens: res });
+            };
+            res.push((token, pos, end));
+            pos = end;
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod lexer_test {
+    use super::*;
+    use crate::parse;
+
+    const IF: usize = 0;
+    const FOR: usize = 1;
+    const IDENT: usize = 2;
+    const SPACE: usize = 3;
+    const NUMBER: usize = 4;
+
+    #[test]
+    fn tokenize_test() {
+        let lexer = Lexer::builder()
+            .add_rule(parse("if").unwrap(), IF)
+            .add_rule(parse("for").unwrap(), FOR)
+            .add_rule(parse("[a-z]([a-z]|([0-9]))*").unwrap(), IDENT)
+            .add_rule(parse(" +").unwrap(), SPACE)
+            .add_rule(parse("[0-9]+").unwrap(), NUMBER)
+            .build();
+        assert_eq!(
+            lexer.tokenize(b"if iffy for  fo 42x1").unwrap(),
+            vec![
+                (IF, 0, 2),
+                (SPACE, 2, 3),
+                (IDENT, 3, 7),
+                (SPACE, 7, 8),
+                (FOR, 8, 11),
+                (SPACE, 11, 13),
+                (IDENT, 13, 15),
+                (SPACE, 15, 16),
+                (NUMBER, 16, 18),
+                (IDENT, 18, 20),
+            ]
+        );
+        assert!(lexer.tokenize(b"").unwrap().is_empty());
+        let error = lexer.tokenize(b"if!if").unwrap_err();
+        assert_eq!(error.pos, 2);
+        assert_eq!(error.tokens, vec![(IF, 0, 2)]);
+    }
+
+    #[test]
+    fn tokenize_error_test() {
+        let lexer = Lexer::builder()
+            .add_rule(parse("[a-z]+").unwrap(), IDENT)
+            .add_rule(parse(" +").unwrap(), SPACE)
+            .build();
+        let error = lexer.tokenize(b"ab cd#ef").unwrap_err();
+        assert_eq!(
+            error,
+            LexError {
+                pos: 5,
+                tokens: vec![(IDENT, 0, 2), (SPACE, 2, 3), (IDENT, 3, 5)],
+            }
+        );
+        assert_eq!(error.to_string(), "no rule matches at position 5");
+        let error = lexer.tokenize(b"#").unwrap_err();
+        assert_eq!((error.pos, error.tokens.len()), (0, 0));
+    }
+
+    #[test]
+    fn priority_test() {
+        let lexer = Lexer::builder()
+            .add_rule(parse("[a-z]+").unwrap(), IDENT)
+            .add_rule(parse("if").unwrap(), IF)
+            .build();
+        assert_eq!(lexer.tokenize(b"if"), Ok(vec![(IDENT, 0, 2)]));
+
+        let lexer = Lexer::builder()
+            .add_rule(parse("a*").unwrap(), IDENT)
+            .build();
+        let error = lexer.tokenize(b"aab").unwrap_err();
+        assert_eq!((error.pos, error.tokens), (2, vec![(IDENT, 0, 2)]));
+    }
+
+    #[test]
+    fn tie_break_test() {
+        let build = |tie_break| {
+            Lexer::builder()
+                .add_rule(parse("[a-z]+").unwrap(), IDENT)
+                .add_rule(parse("for").unwrap(), FOR)
+                .add_rule(parse(" ").unwrap(), SPACE)
+                .tie_break(tie_break)
+                .build()
+        };
+        let input = b"for fork";
+        assert_eq!(
+            build(TieBreak::FirstRule).tokenize(input),
+            Ok(vec![(IDENT, 0, 3), (SPACE, 3, 4), (IDENT, 4, 8)])
+        );
+        assert_eq!(
+            build(TieBreak::LastRule).tokenize(input),
+            Ok(vec![(FOR, 0, 3), (SPACE, 3, 4), (IDENT, 4, 8)])
+        );
+
+        let lexer = Lexer::builder()
+            .add_rule(parse("[a-z]+").unwrap(), IDENT)
+            .add_rule_with_priority(parse("if|for").unwrap(), FOR, 1)
+            .add_rule_with_priority(parse("if").unwrap(), IF, 2)
+            .tie_break(TieBreak::Priority)
+            .build();
+        assert_eq!(lexer.tokenize(b"ifforx"), Ok(vec![(IDENT, 0, 6)]));
+        assert_eq!(lexer.tokenize(b"if"), Ok(vec![(IF, 0, 2)]));
+        assert_eq!(lexer.tokenize(b"for"), Ok(vec![(FOR, 0, 3)]));
+        assert_eq!(lexer.tokenize(b"fo"), Ok(vec![(IDENT, 0, 2)]));
+    }
+}
@@ -1,7 +1,52 @@
+#![cfg_attr(all(feature = "no_std", not(test)), no_std)]
+
+extern crate alloc;
+
 mod automata;
 mod charsets;
+pub mod codegen;
+mod compile;
+mod lexer;
+mod matcher;
+mod parser;
+mod product;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+mod tdfa;
+mod tnfa;
 mod utnfa;
 
-pub use automata::Automata;
-pub use charsets::{Charset, Utf8Charset};
+pub use automata::{Automata, SimpleAutomata, StateInfo};
+pub use charsets::{
+    Charset, CharsetIter, DisplayStyle, FromHexError, Utf8Charset, utnfa_from_ranges,
+};
+pub use compile::{COMPILE_MAX_STATES, CompileError, CompiledRegex, compile};
+pub use lexer::{LexError, Lexer, LexerBuilder, TieBreak};
+pub use matcher::{
+    Anchor, Captures, DfaMatcher, Match, Matcher, Semantics, StepResult, StreamMatcher,
+};
+pub use parser::{
+    MAX_REPETITION, MAX_REPETITION_NODES, ParseError, ParseErrorKind, parse, parse_bytes,
+    parse_with_names,
+};
+pub use product::{difference, equivalent, intersect, shuffle};
+pub use tdfa::{DeterminizeError, DeterminizeOptions, Tdfa};
+pub use tnfa::Tnfa;
 pub use utnfa::UTnfa;
+
+/// Collections, that are available both with and without `std`
+mod collections {
+    pub use alloc::collections::{BTreeMap, BTreeSet};
+    #[cfg(feature = "no_std")]
+    pub use hashbrown::{HashMap, HashSet};
+    #[cfg(not(feature = "no_std"))]
+    pub use std::collections::{HashMap, HashSet};
+}
+
+/// Items of `std` prelude, that are not available in `core` prelude
+mod prelude {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
@@ -0,0 +1,678 @@
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::{Automata, Tnfa};
+
+/// Result of a successful match
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    /// Byte offsets `(start, end)` of each capturing group, `None` if the group didn't participate.
+    /// Group `k` is delimited by tags `2k` and `2k + 1`, group 0 is the whole match
+    pub groups: Vec<Option<(usize, usize)>>,
+}
+
+/// Runs tagged automata over input, recording offsets at which tags are crossed.
+///
+/// Matching is simulated over Tnfa, keeping one thread per state (Pike VM).
+/// When several paths reach the same state, one of them is chosen according to `Semantics`,
+/// which also determines the span of the match, see `with_semantics`.
+///
+/// Epsilon edges, that carry anchor tags (see `Anchor`), are only crossed if the anchor holds
+/// at the current position of input
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    nfa: Tnfa,
+    groups: usize,
+    names: HashMap<String, usize>,
+    semantics: Semantics,
+}
+
+/// Rules of choosing a match and its submatches among all paths, that accept input.
+/// Both semantics prefer matches, that start leftmost, and differ in the rest.
+/// E.g. for `(a|ab)(c|bcd)(d*)` on `abcd` both match the whole input, but `LeftmostLongest`
+/// gives groups `ab`, `c`, `d`, and `LeftmostFirst` gives `a`, `bcd` and empty string
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Semantics {
+    /// POSIX semantics: the longest match is taken. Among paths, that give it,
+    /// groups are compared in order of their indices, and the path, where the group starts
+    /// earlier, or ends later if starts are equal, is preferred. Lazy quantifiers are the same
+    /// as greedy ones. Group offsets are compared as soon as paths meet in the same state,
+    /// so for groups inside loops the choice may differ from POSIX one
+    #[default]
+    LeftmostLongest,
+    /// Perl semantics: paths are ordered by priority, the first path, that accepts, wins,
+    /// even if lower priority paths would give a longer match.
+    /// Edges of union's left branch are preferred over right ones, and repetition is greedy,
+    /// as entering a loop body is preferred over leaving it, unless the repetition is lazy,
+    /// see `UTnfa::kleene_lazy`
+    LeftmostFirst,
+}
+
+/// Match of `Matcher::captures`, that gives access to matched bytes of groups by their indices
+/// or names
+#[derive(Clone, Debug)]
+pub struct Captures<'a> {
+    input: &'a [u8],
+    m: Match,
+    names: &'a HashMap<String, usize>,
+}
+
+/// Matcher, that consumes input one byte at a time, e.g. when input arrives in chunks.
+/// It runs deterministic automata, tracking its current state
+#[derive(Clone, Debug)]
+pub struct StreamMatcher {
+    delta: Vec<[Option<usize>; 256]>,
+    finals: Vec<bool>,
+    live: Vec<bool>,
+    begin: usize,
+    state: Option<usize>,
+}
+
+/// Matcher, that runs deterministic automata over a flat transition table,
+/// so each byte of input is processed in O(1).
+/// The table takes 1 KiB per state, which is a trade-off for speed of matching
+#[derive(Clone, Debug)]
+pub struct DfaMatcher {
+    /// `delta[s][c]` is the state, reached from `s` by char `c`, or `u32::MAX` if there is none
+    delta: Vec<[u32; 256]>,
+    finals: Vec<bool>,
+    begin: u32,
+}
+
+/// Result of feeding a byte to `StreamMatcher`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    /// Some final state is still reachable, so more input may be accepted
+    Live,
+    /// No final state is reachable anymore, so no input will be accepted
+    Dead,
+}
+
+/// Zero-width assertion, carried by an epsilon edge as a reserved negative tag (see `Anchor::tag`).
+/// Anchors are evaluated only by `Matcher`, other consumers of automata treat them as plain
+/// epsilon edges, i.e. as if they always hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    /// `^`, holds at the beginning of input
+    Start,
+    /// `$`, holds at the end of input
+    End,
+    /// `\b`, holds between a word byte (`[0-9A-Za-z_]`) and a non-word byte,
+    /// where beginning and end of input count as non-word bytes
+    WordBoundary,
+}
+
+/// Thread of Pike VM, `tags[t]` is an offset, at which tag `t` was crossed last time.
+/// Thread with `accept` set doesn't consume input, but marks the point, where the match may end,
+/// it's only used by `Semantics::LeftmostFirst` to order ending of the match among other threads
+#[derive(Clone)]
+struct Thread {
+    state: usize,
+    tags: Vec<Option<usize>>,
+    accept: bool,
+}
+
+impl Matcher {
+    /// Creates Matcher, that runs automata `a`
+    pub fn new(a: &impl Automata) -> Self {
+        let max_tag = a.list_edges().map(|e| e.3).max().unwrap_or(-1);
+        Self {
+            nfa: Tnfa::from(a),
+            groups: ((max_tag + 2) / 2).max(1) as usize,
+            names: HashMap::new(),
+            semantics: Semantics::default(),
+        }
+    }
+
+    /// Sets semantics of choosing matches and submatches, default is `Semantics::LeftmostLongest`
+    pub fn with_semantics(mut self, semantics: Semantics) -> Self {
+        self.semantics = semantics;
+        self
+    }
+
+    /// Sets indices of named groups by their names, e.g. as returned by `parse_with_names`
+    pub fn with_names(mut self, names: HashMap<String, usize>) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Same as `matches`, but returns submatches together with `input`, so groups may be
+    /// extracted by their names
+    pub fn captures<'a>(&'a self, input: &'a [u8]) -> Option<Captures<'a>> {
+        let m = self.matches(input)?;
+        Some(Captures {
+            input,
+            m,
+            names: &self.names,
+        })
+    }
+
+    /// Matches the whole `input`, returning submatches on success.
+    /// Unlike `find`, only paths, that consume all of `input`, are considered,
+    /// so with `Semantics::LeftmostFirst` the first of such paths wins
+    pub fn matches(&self, input: &[u8]) -> Option<Match> {
+        self.run(input, 0, true).map(|(_, m)| m)
+    }
+
+    /// Searches for a match anywhere in `input`, following leftmost-longest semantics,
+    /// like `Automata::find`, or leftmost-first one, see `Semantics`.
+    /// Offsets of submatches are relative to the beginning of `input`.
+    /// Anchors are evaluated against the whole `input`, e.g. `^` only holds at offset 0,
+    /// rather than at the beginning of each candidate match
+    pub fn find(&self, input: &[u8]) -> Option<Match> {
+        (0..=input.len()).find_map(|start| self.run(input, start, false).map(|(_, m)| m))
+    }
+
+    /// Returns end and submatches of the match, that starts at `start`, according to semantics.
+    /// If `full` is set, only matches, that end at the end of `input`, are considered
+    fn run(&self, input: &[u8], start: usize, full: bool) -> Option<(usize, Match)> {
+        let mut threads = Vec::new();
+        let begin = Thread {
+            state: self.nfa.begin(),
+            tags: vec![None; 2 * self.groups],
+            accept: false,
+        };
+        self.add_thread(&mut threads, begin, input, start);
+        let mut last = None;
+        for pos in start..=input.len() {
+            if !full || pos == input.len() {
+                last = self.accepted(&mut threads, start, pos).or(last);
+            }
+            if pos == input.len() || threads.is_empty() {
+                break;
+            }
+            let mut next = Vec::new();
+            for t in threads.iter().filter(|t| !t.accept) {
+                for (to, s, tag) in self.nfa.edges(t.state).iter() {
+                    if s.contains(input[pos]) {
+                        let mut tags = t.tags.clone();
+                        if *tag >= 0 {
+                            tags[*tag as usize] = Some(pos + 1);
+                        }
+                        let t = Thread {
+                            state: *to,
+                            tags,
+                            accept: false,
+                        };
+                        self.add_thread(&mut next, t, input, pos + 1);
+                    }
+                }
+            }
+            threads = next;
+        }
+        last
+    }
+
+    /// Returns submatches of the thread, that accepts in `threads`, if any,
+    /// given that input was consumed from `start` to `end`.
+    /// For `Semantics::LeftmostFirst` it's the first accepting thread, and all threads
+    /// after it are dropped, as they have lower priority.
+    /// For `Semantics::LeftmostLongest` it's the best final thread, see `posix_better`
+    fn accepted(
+        &self,
+        threads: &mut Vec<Thread>,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, Match)> {
+        let t = match self.semantics {
+            Semantics::LeftmostFirst => {
+                let i = threads.iter().position(|t| t.accept)?;
+                threads.drain(i..).next()?
+            }
+            Semantics::LeftmostLongest => threads
+                .iter()
+                .filter(|t| self.nfa.is_final(t.state))
+                .fold(None, |best: Option<&Thread>, t| match best {
+                    Some(b) if !posix_better(&t.tags, &b.tags) => Some(b),
+                    _ => Some(t),
+                })?
+                .clone(),
+        };
+        let mut groups = Vec::from_iter((0..self.groups).map(|k| match t.tags[2 * k..2 * k + 2] {
+            [Some(a), Some(b)] => Some((a, b)),
+            _ => None,
+        }));
+        groups[0] = Some((start, end));
+        Some((end, Match { groups }))
+    }
+
+    /// Adds `t` and all threads, reachable from it by epsilon edges, to `threads` in priority order.
+    /// If a state is already present in `threads`, the new thread is skipped,
+    /// unless it's better according to `Semantics::LeftmostLongest`, then it replaces the old one.
+    /// For `Semantics::LeftmostFirst` a final state is followed by an accepting thread,
+    /// which is added after all threads, reachable from the state.
+    /// Edges with anchors, that don't hold at `pos` of `input`, are not crossed
+    fn add_thread(&self, threads: &mut Vec<Thread>, t: Thread, input: &[u8], pos: usize) {
+        let mut stack = vec![t];
+        while let Some(t) = stack.pop() {
+            if t.accept {
+                if !threads.iter().any(|u| u.accept) {
+                    threads.push(t);
+                }
+                continue;
+            }
+            let existing = threads.iter().position(|u| !u.accept && u.state == t.state);
+            match existing {
+                Some(i)
+                    if self.semantics == Semantics::LeftmostLongest
+                        && posix_better(&t.tags, &threads[i].tags) =>
+                {
+                    threads[i].tags.clone_from(&t.tags);
+                }
+                Some(_) => continue,
+                None => {}
+            }
+            if self.semantics == Semantics::LeftmostFirst && self.nfa.is_final(t.state) {
+                stack.push(Thread {
+                    state: t.state,
+                    tags: t.tags.clone(),
+                    accept: true,
+                });
+            }
+            for (to, tag) in self.nfa.eps_edges(t.state).iter().rev() {
+                let mut tags = t.tags.clone();
+                match Anchor::from_tag(*tag) {
+                    Some(a) if !a.holds(input, pos) => continue,
+                    Some(_) => {}
+                    None if *tag != -1 => tags[*tag as usize] = Some(pos),
+                    None => {}
+                }
+                stack.push(Thread {
+                    state: *to,
+                    tags,
+                    accept: false,
+                });
+            }
+            if existing.is_none() {
+                threads.push(t);
+            }
+        }
+    }
+}
+
+/// Returns `true` if thread with tags `a` is better than one with tags `b` by POSIX rules:
+/// groups are compared in order, and the group, that starts earlier, or ends later
+/// if starts are equal, is better. Set offsets are better than unset ones
+fn posix_better(a: &[Option<usize>], b: &[Option<usize>]) -> bool {
+    let key = |t: &[Option<usize>], k: usize| (t[2 * k].map(core::cmp::Reverse), t[2 * k + 1]);
+    (1..a.len() / 2)
+        .map(|k| key(a, k).cmp(&key(b, k)))
+        .find(|o| o.is_ne())
+        .is_some_and(|o| o.is_gt())
+}
+
+impl<'a> Captures<'a> {
+    /// Returns bytes, matched by group `k`, or `None` if the group didn't participate
+    pub fn get(&self, k: usize) -> Option<&'a [u8]> {
+        let (a, b) = (*self.m.groups.get(k)?)?;
+        Some(&self.input[a..b])
+    }
+
+    /// Returns bytes, matched by group named `name`, or `None` if there is no such group,
+    /// or it didn't participate
+    pub fn name(&self, name: &str) -> Option<&'a [u8]> {
+        self.get(*self.names.get(name)?)
+    }
+
+    /// Returns offsets of all groups, see `Match`
+    pub fn as_match(&self) -> &Match {
+        &self.m
+    }
+}
+
+impl Anchor {
+    /// Returns reserved tag, that stands for `self`. Anchor tags are less than `-1`,
+    /// so they never clash with user tags or with absence of a tag
+    pub fn tag(self) -> isize {
+        match self {
+            Anchor::Start => -2,
+            Anchor::End => -3,
+            Anchor::WordBoundary => -4,
+        }
+    }
+
+    /// Returns anchor, that stands for tag `tag`, or `None` if `tag` is not reserved for anchors
+    pub fn from_tag(tag: isize) -> Option<Self> {
+        match tag {
+            -2 => Some(Anchor::Start),
+            -3 => Some(Anchor::End),
+            -4 => Some(Anchor::WordBoundary),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `self` holds at offset `pos` of `input`
+    pub fn holds(self, input: &[u8], pos: usize) -> bool {
+        let is_word = |i: Option<usize>| {
+            i.and_then(|i| input.get(i))
+                .is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_')
+        };
+        match self {
+            Anchor::Start => pos == 0,
+            Anchor::End => pos == input.len(),
+            Anchor::WordBoundary => is_word(pos.checked_sub(1)) != is_word(Some(pos)),
+        }
+    }
+}
+
+impl DfaMatcher {
+    /// Creates DfaMatcher, that runs deterministic automata `dfa`. Tags are ignored.
+    /// Panics if `dfa` is not deterministic or has too many states
+    pub fn new(dfa: &impl Automata) -> Self {
+        assert!(dfa.nodes() < u32::MAX as usize, "too many states");
+        let (delta, finals) = dfa.transition_table();
+        Self {
+            delta: Vec::from_iter(
+                delta
+                    .iter()
+                    .map(|row| row.map(|s| s.map_or(u32::MAX, |s| s as u32))),
+            ),
+            finals,
+            begin: dfa.begin() as u32,
+        }
+    }
+
+    /// Returns `true` if the whole `input` is accepted
+    pub fn matches(&self, input: &[u8]) -> bool {
+        let mut state = self.begin;
+        for c in input.iter() {
+            state = self.delta[state as usize][*c as usize];
+            if state == u32::MAX {
+                return false;
+            }
+        }
+        self.finals[state as usize]
+    }
+
+    /// Returns length of the longest prefix of `input`, that is accepted
+    pub fn longest_prefix(&self, input: &[u8]) -> Option<usize> {
+        let mut state = self.begin;
+        let mut last = self.finals[state as usize].then_some(0);
+        for (i, c) in input.iter().enumerate() {
+            state = self.delta[state as usize][*c as usize];
+            if state == u32::MAX {
+                break;
+            }
+            if self.finals[state as usize] {
+                last = Some(i + 1);
+            }
+        }
+        last
+    }
+}
+
+impl StreamMatcher {
+    /// Creates StreamMatcher, that runs deterministic automata `dfa` from its initial state.
+    /// Tags are ignored. Panics if `dfa` is not deterministic
+    pub fn new(dfa: &impl Automata) -> Self {
+        let (delta, finals) = dfa.transition_table();
+        // live[n] is true if some final state is reachable from n
+        let mut live = finals.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for n in 0..delta.len() {
+                if !live[n] && delta[n].iter().flatten().any(|m| live[*m]) {
+                    live[n] = true;
+                    changed = true;
+                }
+            }
+        }
+        let begin = dfa.begin();
+        Self {
+            state: live[begin].then_some(begin),
+            delta,
+            finals,
+            live,
+            begin,
+        }
+    }
+
+    /// Consumes byte `c`, returning whether input may still be accepted
+    pub fn feed(&mut self, c: u8) -> StepResult {
+        self.state = self
+            .state
+            .and_then(|s| self.delta[s][c as usize])
+            .filter(|s| self.live[*s]);
+        match self.state {
+            None => StepResult::Dead,
+            Some(_) => StepResult::Live,
+        }
+    }
+
+    /// Returns `true` if input, consumed so far, is accepted
+    pub fn is_accepting(&self) -> bool {
+        self.state.is_some_and(|s| self.finals[s])
+    }
+
+    /// Returns `true` if no input will be accepted anymore
+    pub fn is_dead(&self) -> bool {
+        self.state.is_none()
+    }
+
+    /// Returns matcher to its initial state, as if no input was consumed
+    pub fn reset(&mut self) {
+        self.state = self.live[self.begin].then_some(self.begin);
+    }
+}
+
+#[cfg(test)]
+mod matcher_test {
+    use super::*;
+    use crate::{Tdfa, parse, parse_with_names};
+
+    #[test]
+    fn matches_test() {
+        let m = Matcher::new(&parse("(a+)(b+)").unwrap());
+        assert_eq!(
+            m.matches(b"aaabb"),
+            Some(Match {
+                groups: vec![Some((0, 5)), Some((0, 3)), Some((3, 5))]
+            })
+        );
+        assert_eq!(m.matches(b"aaa"), None);
+        assert_eq!(m.matches(b"aaabba"), None);
+
+        let m = Matcher::new(&parse("x(a|(b))*y").unwrap());
+        let res = m.matches(b"xaby").unwrap();
+        assert_eq!(res.groups, vec![Some((0, 4)), Some((2, 3)), Some((2, 3))]);
+        let res = m.matches(b"xbay").unwrap();
+        assert_eq!(res.groups, vec![Some((0, 4)), Some((2, 3)), Some((1, 2))]);
+        let res = m.matches(b"xy").unwrap();
+        assert_eq!(res.groups, vec![Some((0, 2)), None, None]);
+
+        let m = Matcher::new(&parse("(a*)(a*)").unwrap());
+        let res = m.matches(b"aaa").unwrap();
+        assert_eq!(res.groups, vec![Some((0, 3)), Some((0, 3)), Some((3, 3))]);
+    }
+
+    #[test]
+    fn lazy_test() {
+        let groups = |pattern, input| {
+            Matcher::new(&parse(pattern).unwrap())
+                .with_semantics(Semantics::LeftmostFirst)
+                .matches(input)
+                .unwrap()
+                .groups
+        };
+        assert_eq!(groups("(a+?)(a*)", b"aaa")[1], Some((0, 1)));
+        assert_eq!(groups("(a+)(a*)", b"aaa")[1], Some((0, 3)));
+        assert_eq!(groups("(a*?)(a*)", b"aaa")[1], Some((0, 0)));
+        assert_eq!(groups("(a??)(a*)", b"aaa")[1], Some((0, 0)));
+        assert_eq!(groups("(a?)(a*)", b"aaa")[1], Some((0, 1)));
+        assert_eq!(
+            groups("(.*?),(.*)", b"a,b,c")[1..],
+            [Some((0, 1)), Some((2, 5))]
+        );
+        assert_eq!(
+            groups("(.*),(.*)", b"a,b,c")[1..],
+            [Some((0, 3)), Some((4, 5))]
+        );
+
+        // lazy quantifiers make the match shorter only with leftmost-first semantics
+        let m = Matcher::new(&parse("(a+?)").unwrap());
+        assert_eq!(
+            m.find(b"baaa").unwrap().groups,
+            vec![Some((1, 4)), Some((1, 4))]
+        );
+        let m = m.with_semantics(Semantics::LeftmostFirst);
+        assert_eq!(
+            m.find(b"baaa").unwrap().groups,
+            vec![Some((1, 2)), Some((1, 2))]
+        );
+        let m = Matcher::new(&parse("(a*?)(a*)").unwrap());
+        assert_eq!(m.matches(b"aaa").unwrap().groups[1], Some((0, 3)));
+    }
+
+    #[test]
+    fn semantics_test() {
+        let groups = |pattern, input, semantics| {
+            Matcher::new(&parse(pattern).unwrap())
+                .with_semantics(semantics)
+                .find(input)
+                .map(|m| m.groups)
+        };
+        use Semantics::*;
+        assert_eq!(
+            groups("(a|ab)(c|bcd)(d*)", b"abcd", LeftmostLongest),
+            Some(vec![Some((0, 4)), Some((0, 2)), Some((2, 3)), Some((3, 4))])
+        );
+        assert_eq!(
+            groups("(a|ab)(c|bcd)(d*)", b"abcd", LeftmostFirst),
+            Some(vec![Some((0, 4)), Some((0, 1)), Some((1, 4)), Some((4, 4))])
+        );
+        for semantics in [LeftmostLongest, LeftmostFirst] {
+            assert_eq!(
+                groups("(a|ab)(c|bcd)", b"abcd", semantics),
+                Some(vec![Some((0, 4)), Some((0, 1)), Some((1, 4))])
+            );
+            assert_eq!(groups("b+", b"abbbc", semantics), Some(vec![Some((1, 4))]));
+            assert_eq!(groups("x", b"abc", semantics), None);
+        }
+
+        assert_eq!(
+            groups("(a|ab)", b"abc", LeftmostLongest).unwrap()[1],
+            Some((0, 2))
+        );
+        assert_eq!(
+            groups("(a|ab)", b"abc", LeftmostFirst).unwrap()[1],
+            Some((0, 1))
+        );
+        assert_eq!(
+            groups("(ab|a)", b"abc", LeftmostFirst).unwrap()[1],
+            Some((0, 2))
+        );
+        assert_eq!(
+            groups("(a*)(a|b)*", b"aab", LeftmostFirst).unwrap()[1],
+            Some((0, 2))
+        );
+        assert_eq!(
+            groups("(a?)(ab)?b", b"ab", LeftmostLongest).unwrap()[1],
+            Some((0, 1))
+        );
+
+        // a full match is found, even if a higher priority path accepts a prefix
+        let m = Matcher::new(&parse("(a|ab)").unwrap()).with_semantics(LeftmostFirst);
+        assert_eq!(m.matches(b"ab").unwrap().groups[1], Some((0, 2)));
+        let m = Matcher::new(&parse("(a|ab)(c|bcd)(d*)").unwrap()).with_semantics(LeftmostFirst);
+        assert_eq!(m.matches(b"abcd").unwrap().groups[1], Some((0, 1)));
+    }
+
+    #[test]
+    fn captures_test() {
+        let (nfa, names) = parse_with_names("(?P<word>[a-z]+)=(?P<num>[0-9]+)?(x)?").unwrap();
+        let m = Matcher::new(&nfa).with_names(names);
+        let c = m.captures(b"abc=42").unwrap();
+        assert_eq!(c.name("word"), Some(&b"abc"[..]));
+        assert_eq!(c.name("num"), Some(&b"42"[..]));
+        assert_eq!(c.get(0), Some(&b"abc=42"[..]));
+        assert_eq!(c.get(3), None);
+        assert_eq!(c.get(4), None);
+        assert_eq!(c.name("other"), None);
+        assert_eq!(c.as_match().groups[1], Some((0, 3)));
+        let c = m.captures(b"abc=").unwrap();
+        assert_eq!(c.name("num"), None);
+        assert!(m.captures(b"=1").is_none());
+    }
+
+    #[test]
+    fn anchor_test() {
+        let m = Matcher::new(&parse("^abc$").unwrap());
+        assert!(m.matches(b"abc").is_some());
+        assert_eq!(m.find(b"abc").unwrap().groups, vec![Some((0, 3))]);
+        assert_eq!(m.find(b"xabc"), None);
+        assert_eq!(m.find(b"abcx"), None);
+
+        let m = Matcher::new(&parse("\\bword\\b").unwrap());
+        assert_eq!(m.find(b"word").unwrap().groups, vec![Some((0, 4))]);
+        assert_eq!(m.find(b"a word here").unwrap().groups, vec![Some((2, 6))]);
+        assert_eq!(m.find(b"(word)").unwrap().groups, vec![Some((1, 5))]);
+        assert_eq!(m.find(b"swordfish"), None);
+        assert_eq!(m.find(b"words"), None);
+
+        assert!(!Anchor::WordBoundary.holds(b"", 0));
+        assert_eq!(Anchor::from_tag(Anchor::End.tag()), Some(Anchor::End));
+        assert_eq!(Anchor::from_tag(-1), None);
+    }
+
+    #[test]
+    fn find_test() {
+        let m = Matcher::new(&parse("(b+)c").unwrap());
+        let res = m.find(b"abbbcbc").unwrap();
+        assert_eq!(res.groups, vec![Some((1, 5)), Some((1, 4))]);
+        assert_eq!(m.find(b"abab"), None);
+    }
+
+    #[test]
+    fn dfa_matcher_test() {
+        for pattern in ["(a|b)*abb", "[a-c]+x?", "héllo|h.llo|", "a{2,3}(b|c{2})*"] {
+            let nfa = parse(pattern).unwrap();
+            let m = DfaMatcher::new(&Tdfa::determinize(&Tnfa::from(&nfa)));
+            for s in [
+                "", "a", "abb", "babb", "ababa", "cx", "abcx", "xx", "héllo", "hallo", "hello",
+                "aa", "aaa", "aaaa", "aabcc", "aacb",
+            ] {
+                assert_eq!(m.matches(s.as_bytes()), nfa.accepts(s.as_bytes()));
+            }
+        }
+
+        let m = DfaMatcher::new(&Tdfa::determinize(&Tnfa::from(&parse("ab*").unwrap())));
+        assert_eq!(m.longest_prefix(b"abbbc"), Some(4));
+        assert_eq!(m.longest_prefix(b"a"), Some(1));
+        assert_eq!(m.longest_prefix(b"ba"), None);
+        let m = DfaMatcher::new(&Tdfa::determinize(&Tnfa::from(&parse("(ab)*").unwrap())));
+        assert_eq!(m.longest_prefix(b"ababa"), Some(4));
+        assert_eq!(m.longest_prefix(b""), Some(0));
+    }
+
+    #[test]
+    fn stream_matcher_test() {
+        let dfa = Tdfa::determinize(&Tnfa::from(&parse("hello|help").unwrap()));
+        let mut m = StreamMatcher::new(&dfa);
+        assert!(!m.is_accepting());
+        for c in b"hell".iter() {
+            assert_eq!(m.feed(*c), StepResult::Live);
+            assert!(!m.is_accepting());
+        }
+        assert_eq!(m.feed(b'o'), StepResult::Live);
+        assert!(m.is_accepting());
+        // no continuation of `hello` is accepted
+        assert_eq!(m.feed(b'o'), StepResult::Dead);
+        assert!(!m.is_accepting() && m.is_dead());
+        assert_eq!(m.feed(b'h'), StepResult::Dead);
+
+        m.reset();
+        for c in b"hel".iter() {
+            assert_eq!(m.feed(*c), StepResult::Live);
+        }
+        assert_eq!(m.feed(b'x'), StepResult::Dead);
+        m.reset();
+        assert_eq!(m.feed(b'h'), StepResult::Live);
+        assert!(!m.is_dead());
+
+        let dfa = Tdfa::determinize(&Tnfa::from(&parse("a*").unwrap()));
+        let mut m = StreamMatcher::new(&dfa);
+        assert!(m.is_accepting());
+        assert_eq!(m.feed(b'a'), StepResult::Live);
+        assert!(m.is_accepting());
+    }
+}
@@ -0,0 +1,853 @@
+use core::fmt::Display;
+use core::ops::Range;
+use core::str::FromStr;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::{Anchor, Automata, Charset, UTnfa, Utf8Charset};
+
+/// Maximal count of `{n}`, `{n,}` and `{n,m}` quantifiers, as each repetition copies the automata
+pub const MAX_REPETITION: usize = 1000;
+
+/// Maximal number of states of UTnfa, produced by a counted repetition. Unlike `MAX_REPETITION`
+/// it accounts for the size of the repeated automata, so stacked or nested counts,
+/// e.g. `a{1000}{1000}`, can't multiply
+pub const MAX_REPETITION_NODES: usize = 100_000;
+
+/// Error, that occured while parsing a regex
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// Category of the error
+    pub kind: ParseErrorKind,
+    /// Byte range of the offending part of the pattern
+    pub span: Range<usize>,
+    /// The pattern, that failed to parse
+    pub pattern: String,
+}
+
+/// Category of `ParseError`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// `(` without matching `)`, or `)` without matching `(`
+    UnbalancedParen,
+    /// `[` without matching `]`
+    UnterminatedClass,
+    /// Class range `a-b`, where `a > b`
+    InvalidRange,
+    /// Quantifier, that doesn't follow anything to repeat
+    DanglingQuantifier,
+    /// Malformed `{n}`, `{n,}` or `{n,m}` quantifier, `m < n`, a count above `MAX_REPETITION`,
+    /// or a repetition, that would exceed `MAX_REPETITION_NODES`
+    InvalidRepetition,
+    /// Unknown or malformed escape sequence
+    BadEscape,
+    /// `\` at the end of the pattern
+    TrailingBackslash,
+    /// `(?` group, that is not followed by a known group syntax, e.g. `P<name>`
+    UnknownGroup,
+    /// Name of a named group is empty, doesn't start with a letter or `_`,
+    /// contains chars other than ascii alphanumerics and `_`, or is not terminated by `>`
+    InvalidGroupName,
+    /// Two named groups have the same name
+    DuplicateGroupName,
+    /// Char, that doesn't fit into a byte, in pattern of `parse_bytes`
+    NotAByte,
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let message = match self {
+            ParseErrorKind::UnbalancedParen => "unbalanced parenthesis",
+            ParseErrorKind::UnterminatedClass => "unterminated class",
+            ParseErrorKind::InvalidRange => "invalid class range",
+            ParseErrorKind::DanglingQuantifier => "nothing to repeat",
+            ParseErrorKind::InvalidRepetition => "invalid repetition",
+            ParseErrorKind::BadEscape => "bad escape",
+            ParseErrorKind::TrailingBackslash => "trailing backslash",
+            ParseErrorKind::UnknownGroup => "unknown group syntax",
+            ParseErrorKind::InvalidGroupName => "invalid group name",
+            ParseErrorKind::DuplicateGroupName => "duplicate group name",
+            ParseErrorKind::NotAByte => "char is not a byte",
+        };
+        f.write_str(message)
+    }
+}
+
+/// Renders the error message, followed by the pattern and carets under the offending part of it
+impl Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let offset = self.pattern[..self.span.start].chars().count();
+        let width = self.pattern[self.span.clone()].chars().count().max(1);
+        writeln!(f, "{} at position {}", self.kind, self.span.start)?;
+        writeln!(f, "{}", self.pattern)?;
+        write!(f, "{}{}", " ".repeat(offset), "^".repeat(width))
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Compiles regex `pattern` into UTnfa.
+/// Supported syntax:
+/// - literal chars, that are matched as their utf-8 representation
+/// - `.`, that matches any char
+/// - `[...]` and `[^...]` classes of chars and char ranges. `[^...]` is inverted within unicode
+///   scalar values, rather than bytes, so it matches utf-8 representation of any other char
+/// - `(...)` capturing groups, numbered from 1 in order of their opening parentheses.
+///   Group `k` is surrounded by tags `2k` (before the group) and `2k + 1` (after the group).
+///   Tags `0` and `1` are reserved for the whole match, and are not emitted by the parser
+/// - `(?P<name>...)` named capturing groups, which are numbered as unnamed ones,
+///   see `parse_with_names` to get their indices
+/// - `(?:...)` non-capturing groups
+/// - `(?i)` flag, that makes the rest of the enclosing group (or of the whole pattern)
+///   case-insensitive, including all following alternatives, and `(?i:...)` non-capturing group,
+///   that is case-insensitive only inside. Literals and classes are folded by `Utf8Charset::case_fold`,
+///   or by `Charset::case_fold` for `parse_bytes`
+/// - `|` alternation
+/// - `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}` quantifiers, and lazy `*?`, `+?` and `??` ones,
+///   which only affect `Matcher` with `Semantics::LeftmostFirst`. Counts must not exceed
+///   `MAX_REPETITION`, and repeated automata must not exceed `MAX_REPETITION_NODES` states
+/// - escapes `\n`, `\r`, `\t`, `\0`, `\xNN`, and escaped punctuation, e.g. `\*`
+/// - anchors `^`, `$` and `\b`, which are emitted as epsilon edges with anchor tags, see `Anchor`
+pub fn parse(pattern: &str) -> Result<UTnfa, ParseError> {
+    Parser::new(pattern, false).parse()
+}
+
+/// Same as `parse`, but also returns indices of named groups by their names
+pub fn parse_with_names(pattern: &str) -> Result<(UTnfa, HashMap<String, usize>), ParseError> {
+    let mut parser = Parser::new(pattern, false);
+    let nfa = parser.parse()?;
+    Ok((nfa, parser.names))
+}
+
+/// Compiles regex `pattern` into UTnfa, that matches raw bytes instead of utf-8 chars.
+/// The syntax is the same as for `parse`, but:
+/// - `.` matches any byte
+/// - `\xNN` matches byte `NN`, rather than utf-8 representation of char `NN`
+/// - chars `'\0'..='\u{ff}'`, both inside and outside of classes, match a byte of the same value,
+///   other chars are not allowed
+pub fn parse_bytes(pattern: &str) -> Result<UTnfa, ParseError> {
+    Parser::new(pattern, true).parse()
+}
+
+/// Parses body of a byte class without brackets, e.g. `0-9A-Fa-f`, into Charset.
+/// Chars and escapes are the same as in classes of `parse_bytes`, e.g. `\n`, `\xNN` or `\-`.
+/// `-` at the start or at the end of the body is a literal char, `^` is never a negation
+impl FromStr for Charset {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        Parser::new(s, true).class_body()
+    }
+}
+
+/// Recursive descent parser, `pos` is a byte offset of the next char in `s`,
+/// `groups` is the number of capturing groups parsed so far, `names` are indices of named ones,
+/// `bytes` is `true` if chars stand for single bytes, see `parse_bytes`,
+/// `fold` is `true` if case-insensitive flag is currently set
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+    groups: usize,
+    names: HashMap<String, usize>,
+    bytes: bool,
+    fold: bool,
+}
+
+impl Parser<'_> {
+    fn new(s: &str, bytes: bool) -> Parser<'_> {
+        Parser {
+            s,
+            pos: 0,
+            groups: 0,
+            names: HashMap::new(),
+            bytes,
+            fold: false,
+        }
+    }
+
+    fn parse(&mut self) -> Result<UTnfa, ParseError> {
+        let res = self.alternation()?;
+        match self.peek() {
+            None => Ok(res),
+            _ => Err(self.error(self.pos, ParseErrorKind::UnbalancedParen)),
+        }
+    }
+
+    /// Creates error of kind `kind`, that spans from `start` to the current position,
+    /// or covers the char at `start`, if it was not consumed yet
+    fn error(&self, start: usize, kind: ParseErrorKind) -> ParseError {
+        let next = self.s[start..].chars().next().map_or(0, char::len_utf8);
+        self.error_span(start..self.pos.max(start + next), kind)
+    }
+
+    fn error_span(&self, span: Range<usize>, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            kind,
+            span,
+            pattern: self.s.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    /// alternation := concatenation ('|' concatenation)*
+    fn alternation(&mut self) -> Result<UTnfa, ParseError> {
+        let mut res = self.concatenation()?;
+        while self.eat('|') {
+            res.union(&self.concatenation()?);
+        }
+        Ok(res)
+    }
+
+    /// concatenation := repetition*
+    fn concatenation(&mut self) -> Result<UTnfa, ParseError> {
+        let mut res = UTnfa::empty();
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            res.concat(&self.repetition()?);
+        }
+        Ok(res)
+    }
+
+    /// repetition := atom ('*' | '+' | '?' | '*?' | '+?' | '??' | '{' n (',' m?)? '}')*.
+    /// Runs of `*` and `+` are collapsed into a single operator, as `a**`, `a*+` and `a+*` are
+    /// the same as `a*`, and `a++` is the same as `a+`, so no redundant epsilon loops are created.
+    /// Lazy quantifiers `*?`, `+?` and `??` are never collapsed
+    fn repetition(&mut self) -> Result<UTnfa, ParseError> {
+        let mut res = self.atom()?;
+        // pending `*` or `+`, that is applied to `res` at the end of the run
+        let mut closure = None;
+        loop {
+            let pos = self.pos;
+            let lazy = self.s[self.pos..].get(1..2) == Some("?");
+            match self.peek() {
+                Some(c @ ('*' | '+' | '?')) if lazy => {
+                    Self::close(&mut res, closure.take());
+                    match c {
+                        '*' => res.kleene_lazy(),
+                        '+' => res.plus_lazy(),
+                        _ => res.optional_lazy(),
+                    }
+                    self.next();
+                }
+                Some(c @ ('*' | '+')) => {
+                    closure = match (closure, c) {
+                        (None, c) | (Some('+'), c @ '+') => Some(c),
+                        _ => Some('*'),
+                    }
+                }
+                Some('?') => {
+                    Self::close(&mut res, closure.take());
+                    res.optional()
+                }
+                Some('{') => {
+                    Self::close(&mut res, closure.take());
+                    self.next();
+                    let (n, m) = self.counts(pos)?;
+                    if res.nodes().saturating_mul(m.unwrap_or(n) + 1) > MAX_REPETITION_NODES {
+                        return Err(self.error(pos, ParseErrorKind::InvalidRepetition));
+                    }
+                    let mut rest = res.clone();
+                    res.repeat(n);
+                    match m {
+                        None => {
+                            rest.kleene();
+                            res.concat(&rest);
+                        }
+                        Some(m) => {
+                            rest.optional();
+                            for _ in n..m {
+                                res.concat(&rest);
+                            }
+                        }
+                    }
+                    continue;
+                }
+                _ => {
+                    Self::close(&mut res, closure);
+                    return Ok(res);
+                }
+            }
+            self.next();
+        }
+    }
+
+    /// Applies pending `*` or `+` operator `closure` to `nfa`
+    fn close(nfa: &mut UTnfa, closure: Option<char>) {
+        match closure {
+            Some('*') => nfa.kleene(),
+            Some('+') => nfa.plus(),
+            _ => {}
+        }
+    }
+
+    /// Parses counts of `{n}`, `{n,}` or `{n,m}` quantifier after `{`, that starts at `pos`.
+    /// `None` stands for unbounded number of repetitions
+    fn counts(&mut self, pos: usize) -> Result<(usize, Option<usize>), ParseError> {
+        let n = self
+            .number()
+            .ok_or_else(|| self.error(pos, ParseErrorKind::InvalidRepetition))?;
+        let m = match self.eat(',') {
+            false => Some(n),
+            true if self.peek() == Some('}') => None,
+            true => Some(
+                self.number()
+                    .ok_or_else(|| self.error(pos, ParseErrorKind::InvalidRepetition))?,
+            ),
+        };
+        if !self.eat('}') || m.is_some_and(|m| m < n) || m.unwrap_or(n) > MAX_REPETITION {
+            return Err(self.error(pos, ParseErrorKind::InvalidRepetition));
+        }
+        Ok((n, m))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let len = self.s[self.pos..]
+            .bytes()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        let n = self.s[self.pos..self.pos + len].parse().ok()?;
+        self.pos += len;
+        Some(n)
+    }
+
+    /// atom := '(' ('?P<' name '>')? alternation ')' | '(?' flags (')' | ':' alternation ')') | '[' class ']' | '.' | '^' | '$' | '\\b' | escape | char
+    fn atom(&mut self) -> Result<UTnfa, ParseError> {
+        let pos = self.pos;
+        match self.next() {
+            Some('(') if self.peek() == Some('?') && !self.s[self.pos..].starts_with("?P") => {
+                self.next();
+                self.flags(pos)
+            }
+            Some('(') => {
+                self.groups += 1;
+                if self.eat('?') {
+                    self.group_name(pos)?;
+                }
+                let tag = 2 * self.groups as isize;
+                let mut res = UTnfa::tag(tag);
+                res.concat(&self.scoped(self.fold)?);
+                res.concat(&UTnfa::tag(tag + 1));
+                match self.eat(')') {
+                    true => Ok(res),
+                    false => Err(self.error(pos, ParseErrorKind::UnbalancedParen)),
+                }
+            }
+            Some('[') => self.class(pos),
+            Some('.') if self.bytes => Ok(UTnfa::any_byte()),
+            Some('.') => Ok(UTnfa::any_char()),
+            Some('*' | '+' | '?' | '{') => Err(self.error(pos, ParseErrorKind::DanglingQuantifier)),
+            Some('^') => Ok(UTnfa::anchor(Anchor::Start)),
+            Some('$') => Ok(UTnfa::anchor(Anchor::End)),
+            Some('\\') if self.eat('b') => Ok(UTnfa::anchor(Anchor::WordBoundary)),
+            Some('\\') => {
+                let c = self.escape(pos)?;
+                self.literal(pos, c)
+            }
+            Some(c) => self.literal(pos, c),
+            None => unreachable!(),
+        }
+    }
+
+    /// Parses flags after `(?` of a group, that starts at `pos`. `(?flags)` sets flags till the end
+    /// of the enclosing group and matches the empty string, `(?flags:...)` sets them only inside
+    fn flags(&mut self, pos: usize) -> Result<UTnfa, ParseError> {
+        let mut fold = self.fold;
+        loop {
+            match self.next() {
+                Some('i') => fold = true,
+                Some(')') => {
+                    self.fold = fold;
+                    return Ok(UTnfa::empty());
+                }
+                Some(':') => {
+                    let res = self.scoped(fold)?;
+                    return match self.eat(')') {
+                        true => Ok(res),
+                        false => Err(self.error(pos, ParseErrorKind::UnbalancedParen)),
+                    };
+                }
+                _ => return Err(self.error(pos, ParseErrorKind::UnknownGroup)),
+            }
+        }
+    }
+
+    /// Parses alternation with case-insensitive flag set to `fold`, and restores the flag after it
+    fn scoped(&mut self, fold: bool) -> Result<UTnfa, ParseError> {
+        let outer = core::mem::replace(&mut self.fold, fold);
+        let res = self.alternation();
+        self.fold = outer;
+        res
+    }
+
+    /// Parses `P<name>` after `(?` of a group, that starts at `pos`, and records its index
+    fn group_name(&mut self, pos: usize) -> Result<(), ParseError> {
+        if !self.eat('P') || !self.eat('<') {
+            return Err(self.error(pos, ParseErrorKind::UnknownGroup));
+        }
+        let start = self.pos;
+        let len = self.s[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(self.s.len() - start);
+        self.pos += len;
+        let name = &self.s[start..self.pos];
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || !self.eat('>') {
+            return Err(self.error(start, ParseErrorKind::InvalidGroupName));
+        }
+        if self.names.insert(name.to_string(), self.groups).is_some() {
+            return Err(self.error_span(start..self.pos - 1, ParseErrorKind::DuplicateGroupName));
+        }
+        Ok(())
+    }
+
+    /// Creates UTnfa, that matches char `c`, that starts at `pos`
+    fn literal(&self, pos: usize, c: char) -> Result<UTnfa, ParseError> {
+        match (self.bytes, self.fold) {
+            (false, false) => Ok(c.into()),
+            (false, true) => Ok(Utf8Charset::from_iter([(c, c)]).case_fold().into()),
+            (true, _) => Charset::try_from(c)
+                .map(|c| UTnfa::charset(if self.fold { c.case_fold() } else { c }))
+                .map_err(|_| self.error(pos, ParseErrorKind::NotAByte)),
+        }
+    }
+
+    /// class := '^'? (item ('-' item)?)* ']', where the first item may be `]`
+    fn class(&mut self, pos: usize) -> Result<UTnfa, ParseError> {
+        let invert = self.eat('^');
+        // ranges with spans of their items
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let item = self.pos;
+            let lo = match self.next() {
+                None => return Err(self.error(pos, ParseErrorKind::UnterminatedClass)),
+                Some(']') if !first => break,
+                Some('\\') => self.escape(item)?,
+                Some(lo) => lo,
+            };
+            first = false;
+            if self.peek() != Some('-') || self.s[self.pos + 1..].starts_with(']') {
+                ranges.push((item..self.pos, lo, lo));
+                continue;
+            }
+            self.next();
+            let hi = match self.next() {
+                None => return Err(self.error(pos, ParseErrorKind::UnterminatedClass)),
+                Some('\\') => self.escape(self.pos - 1)?,
+                Some(hi) => hi,
+            };
+            if lo > hi {
+                return Err(self.error(item, ParseErrorKind::InvalidRange));
+            }
+            ranges.push((item..self.pos, lo, hi));
+        }
+
+        if !self.bytes {
+            let mut c = Utf8Charset::from_iter(ranges.iter().map(|(_, lo, hi)| (*lo, *hi)));
+            c.invert(invert);
+            return Ok(if self.fold { c.case_fold() } else { c }.into());
+        }
+        let mut c = Charset::empty();
+        for (item, lo, hi) in ranges {
+            match (u8::try_from(lo), u8::try_from(hi)) {
+                (Ok(lo), Ok(hi)) => c |= Charset::from_range((lo, hi)),
+                _ => return Err(self.error_span(item, ParseErrorKind::NotAByte)),
+            }
+        }
+        if self.fold {
+            c = c.case_fold();
+        }
+        Ok(UTnfa::charset(if invert { !c } else { c }))
+    }
+
+    /// class_body := (item ('-' item)?)*, until the end of input, see `Charset::from_str`
+    fn class_body(&mut self) -> Result<Charset, ParseError> {
+        let mut res = Charset::empty();
+        while self.peek().is_some() {
+            let item = self.pos;
+            let lo = self.class_char()?;
+            let hi = match self.peek() == Some('-') && self.pos + 1 < self.s.len() {
+                true => {
+                    self.next();
+                    self.class_char()?
+                }
+                false => lo,
+            };
+            if lo > hi {
+                return Err(self.error(item, ParseErrorKind::InvalidRange));
+            }
+            match (u8::try_from(lo), u8::try_from(hi)) {
+                (Ok(lo), Ok(hi)) => res |= Charset::from_range((lo, hi)),
+                _ => return Err(self.error(item, ParseErrorKind::NotAByte)),
+            }
+        }
+        Ok(res)
+    }
+
+    /// Parses a single char or escape sequence of a class, input must not be empty
+    fn class_char(&mut self) -> Result<char, ParseError> {
+        let pos = self.pos;
+        match self.next() {
+            Some('\\') => self.escape(pos),
+            Some(c) => Ok(c),
+            None => unreachable!(),
+        }
+    }
+
+    /// Parses escape sequence after `\`, that starts at `pos`
+    fn escape(&mut self, pos: usize) -> Result<char, ParseError> {
+        match self.next() {
+            None => Err(self.error(pos, ParseErrorKind::TrailingBackslash)),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('0') => Ok('\0'),
+            Some('x') => {
+                let hex = self.s.get(self.pos..self.pos + 2);
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    None => Err(self.error(pos, ParseErrorKind::BadEscape)),
+                    Some(c) => {
+                        self.pos += 2;
+                        Ok(c as char)
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_punctuation() => Ok(c),
+            Some(_) => Err(self.error(pos, ParseErrorKind::BadEscape)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod parser_test {
+    use super::*;
+    use crate::{Automata, Tdfa, Tnfa, equivalent};
+    use itertools::Itertools;
+
+    #[test]
+    fn parse_test() {
+        let a = parse("a(b|c)*d").unwrap();
+        for s in ["ad", "abd", "acd", "abccbd"] {
+            assert!(a.accepts(s.as_bytes()));
+        }
+        for s in ["", "a", "d", "abc", "aad", "abdd"] {
+            assert!(!a.accepts(s.as_bytes()));
+        }
+
+        let a = parse("[a-z]+").unwrap();
+        assert!(a.accepts(b"a"));
+        assert!(a.accepts(b"hello"));
+        assert!(!a.accepts(b""));
+        assert!(!a.accepts(b"Hello"));
+        assert!(!a.accepts(b"a1"));
+
+        let a = parse("x?[^a-y]{2,3}|é.").unwrap();
+        assert!(a.accepts(b"zz"));
+        assert!(a.accepts(b"xzzz"));
+        assert!(a.accepts("é!".as_bytes()));
+        assert!(a.accepts("éé".as_bytes()));
+        assert!(!a.accepts(b"xz"));
+        assert!(!a.accepts(b"zzzz"));
+        assert!(!a.accepts(b"ab"));
+
+        let a = parse("a{2,}|[]-]\\.\\x41|").unwrap();
+        assert!(a.accepts(b""));
+        assert!(a.accepts(b"aa"));
+        assert!(a.accepts(b"aaaaa"));
+        assert!(a.accepts(b"].A"));
+        assert!(a.accepts(b"-.A"));
+        assert!(!a.accepts(b"a"));
+        assert!(!a.accepts(b"]xA"));
+    }
+
+    #[test]
+    fn negated_class_test() {
+        let a = parse("[^a]").unwrap();
+        for s in ["b", "é", "中", "😀", "\0", "\u{7f}", "\u{80}", "\u{10ffff}"] {
+            assert!(a.accepts(s.as_bytes()), "{s:?}");
+        }
+        assert!(!a.accepts(b"a") && !a.accepts(b"") && !a.accepts("bé".as_bytes()));
+        // only valid utf-8 is matched, neither single bytes nor surrogates
+        for s in [
+            &[0x80][..],
+            &[0xff],
+            &[0xc3],
+            &[0xed, 0xa0, 0x80],
+            &[0xf4, 0x90, 0x80, 0x80],
+        ] {
+            assert!(!a.accepts(s), "{s:?}");
+        }
+
+        let a = parse("[^a-zé]+").unwrap();
+        assert!(a.accepts("AÉ中1".as_bytes()));
+        assert!(!a.accepts("Aé".as_bytes()) && !a.accepts(b"Ab"));
+        assert!(parse_bytes("[^a]").unwrap().accepts(&[0xff]));
+    }
+
+    #[test]
+    fn parse_bytes_test() {
+        let a = parse_bytes("[\\x00-\\x1f]\\xff.").unwrap();
+        assert!(a.accepts(&[0x00, 0xff, 0x80]));
+        assert!(a.accepts(&[0x1f, 0xff, 0xff]));
+        assert!(a.accepts(&[0x10, 0xff, 0x00]));
+        assert!(!a.accepts(&[0x20, 0xff, 0x00]));
+        assert!(!a.accepts(&[0x00, 0xc3, 0xbf, 0x00]));
+        assert!(!a.accepts(&[0x00, 0xff, 0xc3, 0xa9]));
+
+        let a = parse_bytes("[^a]+|a(\\x00|é)").unwrap();
+        assert!(a.accepts(&[0xff, 0xfe, 0x00]));
+        assert!(a.accepts(&[b'a', 0x00]));
+        assert!(a.accepts(&[b'a', 0xe9]));
+        assert!(!a.accepts("aé".as_bytes()));
+        assert!(!a.accepts(b"ba"));
+
+        // the same pattern in utf-8 mode
+        let a = parse("\\xff.").unwrap();
+        assert!(a.accepts("\u{ff}é".as_bytes()));
+        assert!(!a.accepts(&[0xff, 0x00]));
+
+        let error = parse_bytes("ab€").unwrap_err();
+        assert_eq!((error.kind, error.span), (ParseErrorKind::NotAByte, 2..5));
+        let error = parse_bytes("[a-€]").unwrap_err();
+        assert_eq!((error.kind, error.span), (ParseErrorKind::NotAByte, 1..6));
+        assert_eq!(
+            parse_bytes("(a").unwrap_err().kind,
+            ParseErrorKind::UnbalancedParen
+        );
+    }
+
+    #[test]
+    fn groups_test() {
+        let a = parse("(a)(b)").unwrap();
+        let tags = Vec::from_iter(a.list_edges().filter(|e| e.3 != -1).map(|e| e.3).sorted());
+        assert_eq!(tags, [2, 3, 4, 5]);
+
+        let dfa = Tdfa::determinize(&Tnfa::from(&a));
+        assert_eq!(dfa.tag_op(dfa.begin_op()), [2]);
+        let mut state = dfa.begin();
+        for (c, op) in [(b'a', &[3, 4][..]), (b'b', &[5][..])] {
+            let e = dfa.list_edges().find(|e| e.0 == state && e.2 == Some(c));
+            let (_, to, _, t) = e.unwrap();
+            assert_eq!(dfa.tag_op(t), op);
+            state = to;
+        }
+        assert!(dfa.is_final(state));
+
+        let a = parse("((a)|b)*").unwrap();
+        let tags = Vec::from_iter(a.list_edges().filter(|e| e.3 != -1).map(|e| e.3).sorted());
+        assert_eq!(tags, [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn group_repetition_test() {
+        let a = parse("(ab)+").unwrap();
+        for (s, ok) in [("ab", true), ("abab", true), ("", false), ("aba", false)] {
+            assert_eq!(a.accepts(s.as_bytes()), ok);
+        }
+        let a = parse("(a|b)*c").unwrap();
+        for (s, ok) in [("c", true), ("abbac", true), ("ab", false), ("acc", false)] {
+            assert_eq!(a.accepts(s.as_bytes()), ok);
+        }
+        let a = parse("((a)*)+").unwrap();
+        for (s, ok) in [("", true), ("aaa", true), ("b", false)] {
+            assert_eq!(a.accepts(s.as_bytes()), ok);
+        }
+        let a = parse("(a(bc)?){2}").unwrap();
+        for (s, ok) in [
+            ("aa", true),
+            ("abca", true),
+            ("abcabc", true),
+            ("abc", false),
+        ] {
+            assert_eq!(a.accepts(s.as_bytes()), ok);
+        }
+    }
+
+    #[test]
+    fn repetition_idempotence_test() {
+        let star = parse("a*").unwrap();
+        for pattern in ["a**", "a*+", "a+*", "a*+*+"] {
+            let a = parse(pattern).unwrap();
+            assert!(equivalent(&a, &star));
+            assert_eq!(a.nodes(), star.nodes());
+            assert!(!a.has_eps_cycle());
+        }
+        let plus = parse("a+").unwrap();
+        let a = parse("a++").unwrap();
+        assert!(equivalent(&a, &plus));
+        assert_eq!(a.nodes(), plus.nodes());
+        assert!(equivalent(&parse("a*?").unwrap(), &star));
+        assert!(equivalent(&parse("a+{2}").unwrap(), &parse("aa+").unwrap()));
+    }
+
+    #[test]
+    fn named_groups_test() {
+        let (a, names) = parse_with_names("(a)(?P<word>[a-z]+)(?P<_2>b)?").unwrap();
+        assert_eq!(
+            names,
+            HashMap::from([("word".to_string(), 2), ("_2".to_string(), 3)])
+        );
+        let tags = Vec::from_iter(a.list_edges().filter(|e| e.3 != -1).map(|e| e.3).sorted());
+        assert_eq!(tags, [2, 3, 4, 5, 6, 7]);
+        assert!(a.accepts(b"aword") && a.accepts(b"axb") && !a.accepts(b"a"));
+        assert!(parse_with_names("(a)").unwrap().1.is_empty());
+
+        use ParseErrorKind::*;
+        let error = |pattern| {
+            let e = parse(pattern).unwrap_err();
+            (e.kind, e.span)
+        };
+        assert_eq!(error("(?x)"), (UnknownGroup, 0..3));
+        assert_eq!(error("(?i"), (UnknownGroup, 0..3));
+        assert_eq!(error("a(?P<>a)"), (InvalidGroupName, 5..6));
+        assert_eq!(error("(?P<1a>a)"), (InvalidGroupName, 4..6));
+        assert_eq!(error("(?P<a-b>a)"), (InvalidGroupName, 4..5));
+        assert_eq!(error("(?P<ab"), (InvalidGroupName, 4..6));
+        assert_eq!(error("(?P<x>a)(?P<x>b)"), (DuplicateGroupName, 12..13));
+    }
+
+    #[test]
+    fn case_insensitive_test() {
+        let check = |pattern, accepted: &[&str], rejected: &[&str]| {
+            let a = parse(pattern).unwrap();
+            assert!(
+                accepted.iter().all(|s| a.accepts(s.as_bytes())),
+                "{pattern}"
+            );
+            assert!(
+                rejected.iter().all(|s| !a.accepts(s.as_bytes())),
+                "{pattern}"
+            );
+        };
+        check("(?i)abc", &["abc", "ABC", "aBc", "AbC"], &["abd", "ab"]);
+        check("a(?i)bc", &["abc", "aBC"], &["ABC"]);
+        check("a(?i:b)c", &["abc", "aBc"], &["aBC", "Abc"]);
+        check("(a(?i)b)c|d", &["aBc", "abc", "d"], &["abC", "D"]);
+        check("a(?i)b|c", &["aB", "C"], &["AB"]);
+        check("(?i)[a-c]x[^d]", &["AXe", "bxE"], &["dxe", "axd", "axD"]);
+        check("(?i)éσ", &["éσ", "ÉΣ"], &["e"]);
+        check("(?:ab)+", &["abab"], &["a"]);
+        assert!(parse("(?:a)(b)").unwrap().tags().len() == 2);
+
+        let a = parse_bytes("(?i)a[b-c]\\xe9").unwrap();
+        assert!(a.accepts(b"AC\xe9") && a.accepts(b"ab\xe9") && !a.accepts(b"ab\xc9"));
+    }
+
+    #[test]
+    fn lazy_test() {
+        for (lazy, greedy) in [
+            ("a*?", "a*"),
+            ("(ab)+?c", "(ab)+c"),
+            ("a??b", "a?b"),
+            ("a+??", "a*"),
+        ] {
+            assert!(equivalent(&parse(lazy).unwrap(), &parse(greedy).unwrap()));
+        }
+        let a = parse("a+?b").unwrap();
+        assert!(a.accepts(b"aab") && !a.accepts(b"b"));
+    }
+
+    #[test]
+    fn anchors_test() {
+        let a = parse("^a\\b|$\\^").unwrap();
+        let tags = Vec::from_iter(a.list_edges().filter(|e| e.3 != -1).map(|e| e.3).sorted());
+        let anchors = [Anchor::Start, Anchor::WordBoundary, Anchor::End];
+        assert_eq!(
+            tags,
+            Vec::from_iter(anchors.map(Anchor::tag).into_iter().sorted())
+        );
+        // anchors are plain epsilon edges for automata, that don't evaluate them
+        assert!(a.accepts(b"a") && a.accepts(b"^"));
+    }
+
+    #[test]
+    fn charset_from_str_test() {
+        let hex: Charset = "0-9A-Fa-f".parse().unwrap();
+        assert_eq!(hex.len(), 22);
+        assert!(b"09afAF".iter().all(|c| hex.contains(*c)));
+        assert!(b"gG/:@`".iter().all(|c| !hex.contains(*c)));
+
+        let control: Charset = "\\x00-\\x1f".parse().unwrap();
+        assert_eq!(control, Charset::from_range((0, 0x1f)));
+
+        let c: Charset = "\\n\\t\\-a-".parse().unwrap();
+        assert_eq!(Vec::from_iter(c.iter()), b"\t\n-a");
+        assert_eq!("^é\\xff".parse(), Ok(crate::byteset![b'^' 0xe9 0xff]));
+        assert_eq!("".parse(), Ok(Charset::empty()));
+
+        use ParseErrorKind::*;
+        let error = |s: &str| {
+            let e = s.parse::<Charset>().unwrap_err();
+            (e.kind, e.span)
+        };
+        assert_eq!(error("a€"), (NotAByte, 1..4));
+        assert_eq!(error("a-\\u"), (BadEscape, 2..4));
+        assert_eq!(error("z-a"), (InvalidRange, 0..3));
+        assert_eq!(error("ab\\"), (TrailingBackslash, 2..3));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        use ParseErrorKind::*;
+        let error = |pattern| {
+            let e = parse(pattern).unwrap_err();
+            (e.kind, e.span)
+        };
+        assert_eq!(error("a(b"), (UnbalancedParen, 1..3));
+        assert_eq!(error("ab)c"), (UnbalancedParen, 2..3));
+        assert_eq!(error("a|[bc"), (UnterminatedClass, 2..5));
+        assert_eq!(error("[a-"), (UnterminatedClass, 0..3));
+        assert_eq!(error("[bz-a]"), (InvalidRange, 2..5));
+        assert_eq!(error("a|*"), (DanglingQuantifier, 2..3));
+        assert_eq!(error("{2}"), (DanglingQuantifier, 0..1));
+        assert_eq!(error("a{3,2}"), (InvalidRepetition, 1..6));
+        assert_eq!(error("a{x}"), (InvalidRepetition, 1..2));
+        assert_eq!(error("a{1001}"), (InvalidRepetition, 1..7));
+        assert_eq!(error("a{0,100000}"), (InvalidRepetition, 1..11));
+        assert_eq!(error("a{1001,}"), (InvalidRepetition, 1..8));
+        assert_eq!(error("a{1000}{1000}"), (InvalidRepetition, 7..13));
+        assert_eq!(error("a{1000}{1000}{1000}"), (InvalidRepetition, 7..13));
+        assert_eq!(
+            error("((a{1000}){1000}){1000}"),
+            (InvalidRepetition, 10..16)
+        );
+        assert_eq!(error("(a{500}b{500}){50,}"), (InvalidRepetition, 14..19));
+        assert!(parse("a{100}{100}").unwrap().accepts(&[b'a'; 10000]));
+        assert!(parse("(ab|cd){1000}").is_ok());
+        assert_eq!(
+            error("a{99999999999999999999999}"),
+            (InvalidRepetition, 1..2)
+        );
+        assert!(
+            parse("a{1000}").is_ok() && parse("a{2,1000}").is_ok() && parse("a{1000,}").is_ok()
+        );
+        assert_eq!(error("ab\\"), (TrailingBackslash, 2..3));
+        assert_eq!(error("\\q"), (BadEscape, 0..2));
+        assert_eq!(error("\\xZZ"), (BadEscape, 0..2));
+
+        assert_eq!(
+            parse("(a").unwrap_err().to_string(),
+            "unbalanced parenthesis at position 0\n(a\n^^"
+        );
+        assert_eq!(
+            parse("é[z-a]").unwrap_err().to_string(),
+            "invalid class range at position 3\né[z-a]\n  ^^^"
+        );
+        assert_eq!(ParseErrorKind::BadEscape.to_string(), "bad escape");
+    }
+}
@@ -0,0 +1,239 @@
+use crate::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::prelude::*;
+use crate::{Automata, SimpleAutomata, Tdfa, Tnfa};
+
+/// Builds automata, that accepts exactly those strings, that are accepted by both `a` and `b`.
+/// Inputs don't have to be deterministic, as they are determinized internally.
+/// The result is deterministic, tags are dropped
+pub fn intersect(a: &impl Automata, b: &impl Automata) -> SimpleAutomata {
+    product(a, b, |a, b| a && b)
+}
+
+/// Builds automata, that accepts exactly those strings, that are accepted by `a`, but not by `b`.
+/// Inputs don't have to be deterministic, as they are determinized internally.
+/// The result is deterministic, tags are dropped
+pub fn difference(a: &impl Automata, b: &impl Automata) -> SimpleAutomata {
+    product(a, b, |a, b| a && !b)
+}
+
+/// Returns `true` if `a` and `b` accept the same language.
+/// Unlike `Automata::eq`, automata don't have to be isomorphic.
+/// Language equivalence is checked as emptiness of symmetric difference of languages
+pub fn equivalent(a: &impl Automata, b: &impl Automata) -> bool {
+    product(a, b, |a, b| a != b).finals.is_empty()
+}
+
+/// Builds automata, that accepts all interleavings (shuffles) of a string, accepted by `a`,
+/// with a string, accepted by `b`, e.g. shuffle of `ab` and `xy` accepts `abxy`, `axby` and `xaby`.
+/// Each edge of the result advances either `a` or `b`, so it is nondeterministic in general.
+/// Epsilon edges are kept, tags are dropped. Only reachable states are included
+pub fn shuffle(a: &impl Automata, b: &impl Automata) -> SimpleAutomata {
+    let (ea, eb) = (out_edges(a), out_edges(b));
+    let begin = (a.begin(), b.begin());
+    let mut index: HashMap<_, _> = HashMap::from([(begin, 0)]);
+    let mut queue = vec![begin];
+    let mut res = SimpleAutomata {
+        begin: 0,
+        nodes: 1,
+        finals: HashSet::new(),
+        edges: Vec::new(),
+    };
+    while let Some((x, y)) = queue.pop() {
+        let from = index[&(x, y)];
+        if a.is_final(x) && b.is_final(y) {
+            res.finals.insert(from);
+        }
+        let steps = ea[x]
+            .iter()
+            .map(|(nx, c)| ((*nx, y), *c))
+            .chain(eb[y].iter().map(|(ny, c)| ((x, *ny), *c)));
+        for (next, c) in steps {
+            let to = *index.entry(next).or_insert_with(|| {
+                queue.push(next);
+                res.nodes += 1;
+                res.nodes - 1
+            });
+            res.edges.push((from, to, c, -1));
+        }
+    }
+    res
+}
+
+/// Builds product automata of determinized `a` and `b`,
+/// where a pair of states is final iff `is_final` holds for finality of the components.
+/// Missing transitions lead to a dead state, that is represented by `None`.
+/// Only reachable states are included, and states that can never become final are skipped
+fn product(
+    a: &impl Automata,
+    b: &impl Automata,
+    is_final: impl Fn(bool, bool) -> bool,
+) -> SimpleAutomata {
+    let (a, b) = (determinize(a), determinize(b));
+    let (da, db) = (transitions(&a), transitions(&b));
+    // whether a pair with dead first (second) component can still become final
+    let dead_a = is_final(false, false) || is_final(false, true);
+    let dead_b = is_final(false, false) || is_final(true, false);
+
+    let begin = (Some(a.begin()), Some(b.begin()));
+    let mut index: HashMap<_, _> = HashMap::from([(begin, 0)]);
+    let mut queue = vec![begin];
+    let mut res = SimpleAutomata {
+        begin: 0,
+        nodes: 1,
+        finals: HashSet::new(),
+        edges: Vec::new(),
+    };
+    while let Some((x, y)) = queue.pop() {
+        let from = index[&(x, y)];
+        if is_final(
+            x.is_some_and(|x| a.is_final(x)),
+            y.is_some_and(|y| b.is_final(y)),
+        ) {
+            res.finals.insert(from);
+        }
+        let chars = BTreeSet::from_iter(
+            x.iter()
+                .flat_map(|x| da[*x].keys())
+                .chain(y.iter().flat_map(|y| db[*y].keys())),
+        );
+        for c in chars {
+            let nx = x.and_then(|x| da[x].get(c).copied());
+            let ny = y.and_then(|y| db[y].get(c).copied());
+            if (nx.is_none() && !dead_a) || (ny.is_none() && !dead_b) {
+                continue;
+            }
+            let to = *index.entry((nx, ny)).or_insert_with(|| {
+                queue.push((nx, ny));
+                res.nodes += 1;
+                res.nodes - 1
+            });
+            res.edges.push((from, to, Some(*c), -1));
+        }
+    }
+    res
+}
+
+fn determinize(a: &impl Automata) -> Tdfa {
+    Tdfa::determinize(&Tnfa::from(a))
+}
+
+/// Returns all edges of `a` without tags as per-state lists of `(to, char)`, with duplicates removed
+fn out_edges(a: &impl Automata) -> Vec<Vec<(usize, Option<u8>)>> {
+    let mut res = vec![BTreeSet::new(); a.nodes()];
+    for (from, to, c, _) in a.list_edges() {
+        res[from].insert((to, c));
+    }
+    res.into_iter().map(Vec::from_iter).collect()
+}
+
+/// Returns transition function of deterministic automata `a` as a per-state map
+fn transitions(a: &impl Automata) -> Vec<BTreeMap<u8, usize>> {
+    let mut res = vec![BTreeMap::new(); a.nodes()];
+    for (from, to, c, _) in a.list_edges() {
+        if let Some(c) = c {
+            res[from].insert(c, to);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod product_test {
+    use super::*;
+    use crate::{Charset, UTnfa, parse};
+
+    #[test]
+    fn intersect_test() {
+        let mut lower = UTnfa::charset(Charset::from_range((b'a', b'z')));
+        let mut rest = lower.clone();
+        rest.kleene();
+        lower.concat(&rest);
+
+        let any = UTnfa::charset(Charset::from_range((0, 255)));
+        let mut three = any.clone();
+        three.concat(&any);
+        three.concat(&any);
+
+        let res = intersect(&lower, &three);
+        assert!(res.is_deterministic());
+        assert_eq!(res.nodes, 4);
+        assert!(res.accepts(b"abc"));
+        assert!(res.accepts(b"xyz"));
+        assert!(!res.accepts(b""));
+        assert!(!res.accepts(b"ab"));
+        assert!(!res.accepts(b"abcd"));
+        assert!(!res.accepts(b"aB1"));
+        assert!(!res.accepts(b"a c"));
+        assert!(lower.accepts(b"abcd") && three.accepts(b"aB1"));
+    }
+
+    #[test]
+    fn difference_test() {
+        let ident = parse("[a-z]+").unwrap();
+        let keyword = parse("if|for").unwrap();
+        let res = difference(&ident, &keyword);
+        assert!(res.is_deterministic());
+        for s in ["x", "ifx", "i", "fo", "forif", "iff"] {
+            assert!(res.accepts(s.as_bytes()));
+        }
+        for s in ["", "if", "for", "IF", "x1"] {
+            assert!(!res.accepts(s.as_bytes()));
+        }
+
+        assert!(difference(&keyword, &ident).finals.is_empty());
+        assert!(equivalent(&difference(&ident, &UTnfa::nothing()), &ident));
+    }
+
+    #[test]
+    fn shuffle_test() {
+        let res = shuffle(&parse("ab").unwrap(), &parse("xy").unwrap());
+        for s in ["abxy", "axby", "axyb", "xaby", "xayb", "xyab"] {
+            assert!(res.accepts(s.as_bytes()), "{s}");
+        }
+        for s in ["", "ab", "xy", "baxy", "bxay", "abyx", "axbyx", "abxyab"] {
+            assert!(!res.accepts(s.as_bytes()), "{s}");
+        }
+
+        let res = shuffle(&parse("a*").unwrap(), &parse("b").unwrap());
+        assert!(equivalent(&res, &parse("a*ba*").unwrap()));
+        assert!(
+            shuffle(&UTnfa::nothing(), &parse("a").unwrap())
+                .finals
+                .is_empty()
+        );
+        assert!(equivalent(
+            &shuffle(&parse("").unwrap(), &parse("a|bc").unwrap()),
+            &parse("a|bc").unwrap()
+        ));
+    }
+
+    #[test]
+    fn equivalent_test() {
+        let (a, b) = (
+            UTnfa::charset(Charset::from_char(b'a')),
+            UTnfa::charset(Charset::from_char(b'b')),
+        );
+
+        let mut x = a.clone();
+        x.union(&b);
+        x.kleene();
+
+        let mut y = UTnfa::charset(Charset::from_range((b'a', b'b')));
+        y.kleene();
+        y.concat(&y.clone());
+
+        assert!(equivalent(&x, &y));
+        assert!(equivalent(&y, &x));
+
+        let mut a_star = a.clone();
+        a_star.kleene();
+        let mut a_plus = a.clone();
+        a_plus.concat(&a_star);
+
+        assert!(equivalent(&a_star, &a_star));
+        assert!(!equivalent(&a_star, &a_plus));
+        assert!(!equivalent(&a_plus, &a_star));
+        assert!(!equivalent(&a_star, &x));
+    }
+}
@@ -0,0 +1,102 @@
+//! Proptest strategies for generating random automata
+
+use proptest::collection::vec;
+use proptest::option::weighted;
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use crate::collections::HashSet;
+use crate::prelude::*;
+use crate::{Charset, SimpleAutomata, UTnfa};
+
+/// Alphabet of generated automata, it's small enough for random strings to be accepted often
+pub const ALPHABET: (u8, u8) = (b'a', b'c');
+
+/// Generates non-empty charset of chars from `ALPHABET`
+pub fn charset() -> impl Strategy<Value = Charset> {
+    let n = ALPHABET.1 - ALPHABET.0 + 1;
+    (1u32..1 << n).prop_map(move |mask| {
+        let mut c = Charset::empty();
+        for i in (0..n).filter(|i| mask & (1 << i) != 0) {
+            c |= Charset::from_char(ALPHABET.0 + i);
+        }
+        c
+    })
+}
+
+/// Generates untagged UTnfa as a random expression of `charset`, `concat`, `union` and `kleene`
+pub fn utnfa() -> impl Strategy<Value = UTnfa> {
+    let leaf = prop_oneof![
+        3 => charset().prop_map(UTnfa::charset),
+        1 => Just(UTnfa::empty()),
+    ];
+    leaf.prop_recursive(4, 32, 2, |inner| {
+        prop_oneof![
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| a + b),
+            (inner.clone(), inner.clone()).prop_map(|(a, b)| a | b),
+            inner.prop_map(|mut a| {
+                a.kleene();
+                a
+            }),
+        ]
+    })
+}
+
+/// Generates untagged SimpleAutomata with at most `max_nodes` nodes, random finals and random edges
+/// over `ALPHABET`, some of them are epsilon edges
+pub fn simple_automata(max_nodes: usize) -> impl Strategy<Value = SimpleAutomata> {
+    (1..=max_nodes).prop_flat_map(|nodes| {
+        let edge = (0..nodes, 0..nodes, weighted(0.8, ALPHABET.0..=ALPHABET.1));
+        (vec(any::<bool>(), nodes), vec(edge, 0..=3 * nodes)).prop_map(move |(finals, edges)| {
+            SimpleAutomata {
+                begin: 0,
+                nodes,
+                finals: HashSet::from_iter((0..nodes).filter(|n| finals[*n])),
+                edges: Vec::from_iter(edges.into_iter().map(|(a, b, c)| (a, b, c, -1))),
+            }
+        })
+    })
+}
+
+/// Chars of generated patterns: literals, digits of repetitions and all syntax chars of `parse`
+pub const PATTERN_CHARS: &str = "abcé019,-()[]{}^$|*+?.\\:<>Pixnb";
+
+/// Generates random strings of at most `max_len` chars from `PATTERN_CHARS`.
+/// Most of them are not valid regexes, which is intended for fuzzing of the parser
+pub fn pattern(max_len: usize) -> impl Strategy<Value = String> {
+    vec(select(Vec::from_iter(PATTERN_CHARS.chars())), 0..=max_len).prop_map(String::from_iter)
+}
+
+#[cfg(test)]
+mod strategies_test {
+    use super::*;
+    use crate::{Automata, Tdfa, Tnfa, equivalent, intersect};
+
+    /// Determinizes `a` and drops tags of the result
+    fn determinize(a: &impl Automata) -> SimpleAutomata {
+        Tdfa::determinize(&Tnfa::from(a)).to_untagged()
+    }
+
+    proptest! {
+        #[test]
+        fn minimize_preserves_language(a in utnfa(), b in simple_automata(5)) {
+            for nfa in [determinize(&a), b] {
+                let dfa = determinize(&nfa);
+                let min = dfa.minimize();
+                prop_assert!(min.nodes() <= dfa.nodes());
+                prop_assert!(equivalent(&nfa, &min));
+                prop_assert_eq!(nfa.matches_up_to(4), min.matches_up_to(4));
+            }
+        }
+
+        #[test]
+        fn intersect_accepts_common_strings(a in utnfa(), b in simple_automata(5)) {
+            let both = intersect(&a, &b);
+            let expected = Vec::from_iter(
+                a.matches_up_to(4).into_iter().filter(|s| b.accepts(s)),
+            );
+            prop_assert_eq!(both.matches_up_to(4), expected);
+            prop_assert!(equivalent(&both, &intersect(&b, &a)));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use core::fmt::Display;
+
+use crate::collections::{BTreeSet, HashMap};
+
+use crate::prelude::*;
+use crate::{Automata, Charset, Tnfa};
+
+/// Represents tagged deterministic automata.
+/// Every edge carries a tag operation, i.e. the set of tags, crossed by the corresponding Tnfa
+/// transitions. Tag operations are identified by their index, `-1` stands for no tags.
+///
+/// Tags are not tracked per Tnfa thread, so a tag operation over-approximates tags of any single
+/// path: it unites tags, crossed by all threads, including ones, that die later.
+/// E.g. for `(a)b|ac` the edge by `a` sets the closing tag of the group, even if input is `ac`.
+/// So a tag operation tells, which tags may have been crossed, but not offsets of submatches,
+/// use `Matcher` to extract them
+#[derive(Clone, Debug)]
+pub struct Tdfa {
+    begin: usize,
+    begin_op: isize,
+    finals: Vec<bool>,
+    edges: Vec<(usize, usize, Charset, isize)>,
+    ops: Vec<Box<[isize]>>,
+}
+
+/// Options of `Tdfa::determinize_with`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeterminizeOptions {
+    /// Maximal number of Tdfa states, `None` means no limit
+    pub max_states: Option<usize>,
+}
+
+/// Error, returned by `Tdfa::determinize_with`, if Tdfa has more states than allowed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeterminizeError {
+    /// The limit, that was exceeded
+    pub max_states: usize,
+}
+
+impl Display for DeterminizeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "determinized automata exceeds {} states",
+            self.max_states
+        )
+    }
+}
+
+impl core::error::Error for DeterminizeError {}
+
+/// Set of Tnfa states, that identifies a Tdfa state during determinization.
+/// States are stored sorted and without duplicates, so equal sets have equal keys,
+/// regardless of the order, in which states were added.
+/// Tags are not a part of the key, as they are carried by tag operations of edges
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct StateSet(Box<[usize]>);
+
+impl FromIterator<usize> for StateSet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        let mut states = Vec::from_iter(iter);
+        states.sort_unstable();
+        states.dedup();
+        StateSet(states.into_boxed_slice())
+    }
+}
+
+impl From<&BTreeSet<usize>> for StateSet {
+    fn from(states: &BTreeSet<usize>) -> Self {
+        // BTreeSet is already sorted and deduplicated
+        StateSet(Box::from_iter(states.iter().copied()))
+    }
+}
+
+impl Tdfa {
+    /// Builds Tdfa from `nfa` using subset construction.
+    /// Each Tdfa state represents epsilon-closure of a set of Tnfa states.
+    /// Labels of edges leaving a state are splitted into disjoint charsets,
+    /// so that every char leads to at most one state.
+    /// Tag operation of an edge is the union of tags, crossed by all Tnfa transitions it stands for
+    pub fn determinize(nfa: &Tnfa) -> Self {
+        Self::determinize_with(nfa, &DeterminizeOptions::default()).expect("no state limit")
+    }
+
+    /// Same as `determinize`, but fails as soon as number of states exceeds `options.max_states`,
+    /// so that patterns, which blow up exponentially, don't exhaust memory
+    pub fn determinize_with(
+        nfa: &Tnfa,
+        options: &DeterminizeOptions,
+    ) -> Result<Self, DeterminizeError> {
+        let max_states = options.max_states.unwrap_or(usize::MAX);
+        let mut dfa = Tdfa {
+            begin: 0,
+            begin_op: -1,
+            finals: Vec::new(),
+            edges: Vec::new(),
+            ops: Vec::new(),
+        };
+        let mut states: HashMap<StateSet, usize> = HashMap::new();
+        let mut queue = Vec::new();
+
+        let (begin, tags) = nfa.eps_closure(&BTreeSet::from([nfa.begin()]));
+        dfa.begin_op = dfa.add_op(tags);
+        if max_states == 0 {
+            return Err(DeterminizeError { max_states });
+        }
+        dfa.finals.push(begin.iter().any(|n| nfa.is_final(*n)));
+        states.insert(StateSet::from(&begin), 0);
+        queue.push((begin, 0));
+
+        while let Some((set, from)) = queue.pop() {
+            for part in Charset::disjoint_partition(&Vec::from_iter(nfa.labels(&set))) {
+                let c = part
+                    .first()
+                    .expect("disjoint_partition yields non-empty charsets");
+                let (moved, mut tags) = nfa.move_on(&set, c);
+                let (closure, closure_tags) = nfa.eps_closure(&moved);
+                tags.extend(closure_tags);
+                let key = StateSet::from(&closure);
+                let to = match states.get(&key) {
+                    Some(to) => *to,
+                    None if dfa.finals.len() == max_states => {
+                        return Err(DeterminizeError { max_states });
+                    }
+                    None => {
+                        let to = dfa.finals.len();
+                        dfa.finals.push(closure.iter().any(|n| nfa.is_final(*n)));
+                        states.insert(key, to);
+                        queue.push((closure, to));
+                        to
+                    }
+                };
+                let op = dfa.add_op(tags);
+                dfa.edges.push((from, to, part, op));
+            }
+        }
+        Ok(dfa)
+    }
+
+    /// Returns tags, set by tag operation `op`. It's a union of tags of all Tnfa threads,
+    /// so it may contain tags of threads, that don't reach the final state, see `Tdfa`
+    pub fn tag_op(&self, op: isize) -> &[isize] {
+        match op {
+            -1 => &[],
+            _ => &self.ops[op as usize],
+        }
+    }
+
+    /// Returns tag operation, that has to be performed before consuming any input
+    pub fn begin_op(&self) -> isize {
+        self.begin_op
+    }
+
+    /// Returns index of tag operation, that sets `tags`, creating it if necessary
+    fn add_op(&mut self, tags: BTreeSet<isize>) -> isize {
+        if tags.is_empty() {
+            return -1;
+        }
+        let tags = Box::from_iter(tags);
+        match self.ops.iter().position(|op| *op == tags) {
+            Some(op) => op as isize,
+            None => {
+                self.ops.push(tags);
+                self.ops.len() as isize - 1
+            }
+        }
+    }
+}
+
+impl Automata for Tdfa {
+    fn begin(&self) -> usize {
+        self.begin
+    }
+
+    fn nodes(&self) -> usize {
+        self.finals.len()
+    }
+
+    fn is_final(&self, n: usize) -> bool {
+        self.finals[n]
+    }
+
+    /// Tag of each edge is the index of its tag operation, see `Tdfa::tag_op`
+    fn list_edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>, isize)> {
+        self.edges
+            .iter()
+            .flat_map(|(a, b, c, t)| c.iter().map(|c| (*a, *b, Some(c), *t)))
+    }
+
+    fn list_range_edges(&self) -> impl Iterator<Item = (usize, usize, Option<Charset>, isize)> {
+        self.edges.iter().map(|(a, b, c, t)| (*a, *b, Some(*c), *t))
+    }
+}
+
+impl<T: Automata> PartialEq<T> for Tdfa {
+    fn eq(&self, other: &T) -> bool {
+        Automata::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tdfa_test {
+    use super::*;
+    use crate::UTnfa;
+    use crate::automata::SimpleAutomata;
+    use crate::collections::HashSet;
+
+    fn char(c: u8) -> UTnfa {
+        UTnfa::charset(Charset::from_char(c))
+    }
+
+    #[test]
+    fn tag_op_over_approximation_test() {
+        let dfa = Tdfa::determinize(&Tnfa::from(&crate::parse("(a)b|ac").unwrap()));
+        let trace = dfa.trace(b"ac").unwrap();
+        // the only accepting path for `ac` doesn't cross group tags, but the edge sets them
+        assert_eq!(dfa.tag_op(dfa.begin_op()), [2]);
+        assert_eq!(dfa.tag_op(trace[1].2), [3]);
+        assert_eq!(dfa.tag_op(trace[2].2), [0isize; 0]);
+    }
+
+    #[test]
+    fn state_set_test() {
+        let mut states = HashMap::new();
+        states.insert(StateSet::from_iter([3, 1, 2]), 0);
+        states.insert(StateSet::from_iter([1, 2, 3]), 1);
+        states.insert(StateSet::from_iter([2, 3, 1, 3, 2]), 2);
+        states.insert(StateSet::from(&BTreeSet::from([2, 1, 3])), 3);
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[&StateSet::from_iter([1, 2, 3])], 3);
+
+        states.insert(StateSet::from_iter([1, 2]), 4);
+        states.insert(StateSet::from_iter([]), 5);
+        assert_eq!(states.len(), 3);
+        assert_ne!(StateSet::from_iter([1, 2]), StateSet::from_iter([1, 2, 3]));
+    }
+
+    #[test]
+    fn determinize_limit_test() {
+        let limit = |n| DeterminizeOptions {
+            max_states: Some(n),
+        };
+        // `(a|b)*a(a|b){n}` needs at least 2^(n+1) states
+        let nfa = Tnfa::from(&crate::parse("(a|b)*a(a|b){12}").unwrap());
+        let error = Tdfa::determinize_with(&nfa, &limit(1000)).unwrap_err();
+        assert_eq!(error, DeterminizeError { max_states: 1000 });
+        assert_eq!(
+            error.to_string(),
+            "determinized automata exceeds 1000 states"
+        );
+
+        let nfa = Tnfa::from(&crate::parse("(a|b)*a(a|b){2}").unwrap());
+        let n = Tdfa::determinize(&nfa).nodes();
+        assert_eq!(Tdfa::determinize_with(&nfa, &limit(n)).unwrap().nodes(), n);
+        assert!(Tdfa::determinize_with(&nfa, &limit(n - 1)).is_err());
+        assert!(Tdfa::determinize_with(&nfa, &limit(0)).is_err());
+        let dfa = Tdfa::determinize_with(&nfa, &DeterminizeOptions::default());
+        assert_eq!(dfa.unwrap().nodes(), n);
+    }
+
+    #[test]
+    fn determinize_test() {
+        let mut bc = char(b'b');
+        bc.union(&char(b'c'));
+        let mut a = char(b'a');
+        a.concat(&bc);
+
+        let dfa = Tdfa::determinize(&Tnfa::from(&a));
+        assert!(!a.is_deterministic());
+        assert!(dfa.is_deterministic());
+        assert_eq!(
+            dfa,
+            SimpleAutomata {
+                begin: 0,
+                nodes: 4,
+                finals: HashSet::from([2, 3]),
+                edges: vec![
+                    (0, 1, Some(b'a'), -1),
+                    (1, 2, Some(b'b'), -1),
+                    (1, 3, Some(b'c'), -1),
+                ],
+            }
+        );
+        assert!(!dfa.is_final(0) && !dfa.is_final(1));
+
+        let mut ab = char(b'a');
+        ab.union(&UTnfa::charset(Charset::from_range((b'a', b'b'))));
+        ab.kleene();
+        let dfa = Tdfa::determinize(&Tnfa::from(&ab));
+        assert!(dfa.is_deterministic());
+        assert!(dfa.is_final(dfa.begin()));
+    }
+
+    #[test]
+    fn tags_test() {
+        let mut a = UTnfa::tag(0);
+        a.concat(&char(b'a'));
+        a.concat(&UTnfa::tag(1));
+        a.concat(&UTnfa::tag(2));
+
+        let dfa = Tdfa::determinize(&Tnfa::from(&a));
+        assert_eq!(dfa.tag_op(dfa.begin_op()), [0]);
+        let edges: Vec<_> = dfa.list_edges().collect();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(dfa.tag_op(edges[0].3), [1, 2]);
+    }
+}
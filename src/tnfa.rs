@@ -0,0 +1,172 @@
+use crate::collections::BTreeSet;
+
+use crate::prelude::*;
+use crate::{Automata, Charset};
+
+/// Represents tagged nondeterministic automata.
+/// Unlike `UTnfa`, edges are stored as per-node adjacency lists, and there may be many final states
+#[derive(Clone, Debug)]
+pub struct Tnfa {
+    begin: usize,
+    finals: Vec<bool>,
+    edges: Vec<Vec<(usize, Charset, isize)>>,
+    eps_edges: Vec<Vec<(usize, isize)>>,
+}
+
+impl Tnfa {
+    /// Returns all states, reachable from `states` by epsilon edges (including `states` themselves),
+    /// and all tags, crossed on the way
+    pub(crate) fn eps_closure(
+        &self,
+        states: &BTreeSet<usize>,
+    ) -> (BTreeSet<usize>, BTreeSet<isize>) {
+        let mut closure = states.clone();
+        let mut tags = BTreeSet::new();
+        let mut stack = Vec::from_iter(states.iter().copied());
+        while let Some(n) = stack.pop() {
+            for (m, t) in self.eps_edges[n].iter() {
+                if *t != -1 {
+                    tags.insert(*t);
+                }
+                if closure.insert(*m) {
+                    stack.push(*m);
+                }
+            }
+        }
+        (closure, tags)
+    }
+
+    /// Returns all states, reachable from `states` by consuming char `c`,
+    /// and all tags, crossed on the way
+    pub(crate) fn move_on(
+        &self,
+        states: &BTreeSet<usize>,
+        c: u8,
+    ) -> (BTreeSet<usize>, BTreeSet<isize>) {
+        let mut res = BTreeSet::new();
+        let mut tags = BTreeSet::new();
+        for n in states.iter() {
+            for (m, s, t) in self.edges[*n].iter() {
+                if s.contains(c) {
+                    res.insert(*m);
+                    if *t != -1 {
+                        tags.insert(*t);
+                    }
+                }
+            }
+        }
+        (res, tags)
+    }
+
+    /// Returns all non-epsilon edges, that leave `n`
+    pub(crate) fn edges(&self, n: usize) -> &[(usize, Charset, isize)] {
+        &self.edges[n]
+    }
+
+    /// Returns all epsilon edges, that leave `n`, in the order of their priority
+    pub(crate) fn eps_edges(&self, n: usize) -> &[(usize, isize)] {
+        &self.eps_edges[n]
+    }
+
+    /// Returns labels of all non-epsilon edges, that leave any of `states`
+    pub(crate) fn labels(&self, states: &BTreeSet<usize>) -> impl Iterator<Item = Charset> {
+        states
+            .iter()
+            .flat_map(|n| self.edges[*n].iter().map(|(_, s, _)| *s))
+    }
+}
+
+impl<T: Automata> From<&T> for Tnfa {
+    fn from(a: &T) -> Self {
+        let mut nfa = Tnfa {
+            begin: a.begin(),
+            finals: (0..a.nodes()).map(|n| a.is_final(n)).collect(),
+            edges: vec![Vec::new(); a.nodes()],
+            eps_edges: vec![Vec::new(); a.nodes()],
+        };
+        for (from, to, c, t) in a.list_range_edges() {
+            match c {
+                None => nfa.eps_edges[from].push((to, t)),
+                Some(c) => match nfa.edges[from].iter_mut().find(|e| e.0 == to && e.2 == t) {
+                    Some(e) => e.1 |= c,
+                    None => nfa.edges[from].push((to, c, t)),
+                },
+            }
+        }
+        nfa
+    }
+}
+
+impl Automata for Tnfa {
+    fn begin(&self) -> usize {
+        self.begin
+    }
+
+    fn nodes(&self) -> usize {
+        self.finals.len()
+    }
+
+    fn is_final(&self, n: usize) -> bool {
+        self.finals[n]
+    }
+
+    fn list_edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>, isize)> {
+        let edges = self.edges.iter().enumerate().flat_map(|(a, e)| {
+            e.iter()
+                .flat_map(move |(b, c, t)| c.iter().map(move |c| (a, *b, Some(c), *t)))
+        });
+        let eps_edges = self
+            .eps_edges
+            .iter()
+            .enumerate()
+            .flat_map(|(a, e)| e.iter().map(move |(b, t)| (a, *b, None, *t)));
+        edges.chain(eps_edges)
+    }
+
+    fn list_range_edges(&self) -> impl Iterator<Item = (usize, usize, Option<Charset>, isize)> {
+        let edges = self
+            .edges
+            .iter()
+            .enumerate()
+            .flat_map(|(a, e)| e.iter().map(move |(b, c, t)| (a, *b, Some(*c), *t)));
+        let eps_edges = self
+            .eps_edges
+            .iter()
+            .enumerate()
+            .flat_map(|(a, e)| e.iter().map(move |(b, t)| (a, *b, None, *t)));
+        edges.chain(eps_edges)
+    }
+}
+
+impl<T: Automata> PartialEq<T> for Tnfa {
+    fn eq(&self, other: &T) -> bool {
+        Automata::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tnfa_test {
+    use super::*;
+    use crate::UTnfa;
+
+    #[test]
+    fn from_utnfa_test() {
+        let mut a = UTnfa::charset(Charset::from_range((b'a', b'c')));
+        a.concat(&UTnfa::tag(1));
+        let nfa = Tnfa::from(&a);
+        assert_eq!(nfa, a);
+
+        let (closure, tags) = nfa.eps_closure(&BTreeSet::from([a.begin()]));
+        assert_eq!(closure, BTreeSet::from([a.begin()]));
+        assert!(tags.is_empty());
+
+        let (moved, _) = nfa.move_on(&closure, b'b');
+        assert_eq!(moved.len(), 1);
+        let (closure, tags) = nfa.eps_closure(&moved);
+        assert_eq!(closure.len(), 3);
+        assert!(closure.iter().any(|n| nfa.is_final(*n)));
+        assert_eq!(tags, BTreeSet::from([1]));
+
+        assert!(nfa.move_on(&BTreeSet::from([a.begin()]), b'd').0.is_empty());
+    }
+}
@@ -1,7 +1,14 @@
-use crate::{Automata, Charset};
+use core::ops::{Add, BitOr, Mul};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::collections::{BTreeSet, HashSet};
+use crate::prelude::*;
+use crate::{Anchor, Automata, Charset, SimpleAutomata, Utf8Charset};
 
 /// Represents uncooked tagged nondetermitistic automata
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UTnfa {
     nodes: usize,
     begin: usize,
@@ -22,8 +29,56 @@ impl UTnfa {
         }
     }
 
-    /// Creates UTnfa to match single char from charset `c`
+    /// Creates UTnfa to match empty string, like `empty()`, with space reserved
+    /// for `edges` non-epsilon and `eps_edges` epsilon edges.
+    /// Nodes are not stored explicitly, so they need no space
+    pub fn with_capacity(edges: usize, eps_edges: usize) -> Self {
+        UTnfa {
+            nodes: 1,
+            begin: 0,
+            end: 0,
+            edges: Vec::with_capacity(edges),
+            eps_edges: Vec::with_capacity(eps_edges),
+        }
+    }
+
+    /// Reserves space for at least `edges` more non-epsilon and `eps_edges` more epsilon edges
+    pub fn reserve(&mut self, edges: usize, eps_edges: usize) {
+        self.edges.reserve(edges);
+        self.eps_edges.reserve(eps_edges);
+    }
+
+    /// Creates UTnfa, that matches nothing, i.e. accepts the empty language
+    pub fn nothing() -> Self {
+        UTnfa {
+            nodes: 2,
+            begin: 0,
+            end: 1,
+            edges: Vec::new(),
+            eps_edges: Vec::new(),
+        }
+    }
+
+    /// Creates UTnfa with `nodes` nodes and non-epsilon `edges`, node 0 is `begin`, node 1 is `end`
+    pub(crate) fn from_edges(nodes: usize, edges: Vec<(usize, usize, Charset)>) -> Self {
+        let nfa = UTnfa {
+            nodes,
+            begin: 0,
+            end: 1,
+            edges,
+            eps_edges: Vec::new(),
+        };
+        #[cfg(debug_assertions)]
+        nfa.validate();
+        nfa
+    }
+
+    /// Creates UTnfa to match single char from charset `c`.
+    /// Empty `c` gives the same automata as `nothing()`, without any edges
     pub fn charset(c: Charset) -> Self {
+        if c.is_empty() {
+            return Self::nothing();
+        }
         UTnfa {
             nodes: 2,
             begin: 0,
@@ -33,8 +88,22 @@ impl UTnfa {
         }
     }
 
-    /// Creates UTnfa to match tag `tag`
+    /// Creates UTnfa to match any single byte
+    pub fn any_byte() -> Self {
+        Self::charset(Charset::full())
+    }
+
+    /// Creates UTnfa to match utf-8 representation of any single char
+    pub fn any_char() -> Self {
+        let mut c = Utf8Charset::empty();
+        c.add_range(('\0', char::MAX));
+        c.into()
+    }
+
+    /// Creates UTnfa to match tag `tag`.
+    /// Panics if `tag` is negative, as `-1` stands for absence of a tag
     pub fn tag(tag: isize) -> Self {
+        assert!(tag >= 0, "tag must be non-negative");
         UTnfa {
             nodes: 2,
             begin: 0,
@@ -44,11 +113,67 @@ impl UTnfa {
         }
     }
 
+    /// Creates UTnfa to match zero-width assertion `anchor`, see `Anchor`
+    pub fn anchor(anchor: Anchor) -> Self {
+        UTnfa {
+            nodes: 2,
+            begin: 0,
+            end: 1,
+            edges: Vec::new(),
+            eps_edges: vec![(0, 1, anchor.tag())],
+        }
+    }
+
+    /// Rebuilds UTnfa from any automata `a`, so it can be further composed.
+    /// Nodes of `a` keep their indices. If `a` has exactly one final state, it becomes `end`,
+    /// otherwise all final states get epsilon edges to a fresh `end` node.
+    /// Non-epsilon edges with tags are split into a charset edge and a tagged epsilon edge
+    pub fn from_automata(a: &impl Automata) -> Self {
+        let mut nfa = UTnfa::empty();
+        nfa.nodes = a.nodes();
+        nfa.begin = a.begin();
+        for (from, to, c, t) in a.list_range_edges() {
+            match (c, t) {
+                (None, t) => nfa.eps_edges.push((from, to, t)),
+                (Some(c), -1) => nfa.edges.push((from, to, c)),
+                (Some(c), t) => {
+                    nfa.edges.push((from, nfa.nodes, c));
+                    nfa.eps_edges.push((nfa.nodes, to, t));
+                    nfa.nodes += 1;
+                }
+            }
+        }
+        let finals = Vec::from_iter(a.final_states());
+        match finals[..] {
+            [end] => nfa.end = end,
+            _ => {
+                nfa.end = nfa.nodes;
+                nfa.nodes += 1;
+                for n in finals {
+                    nfa.eps_edges.push((n, nfa.end, -1));
+                }
+            }
+        }
+        nfa
+    }
+
+    /// Concatenates all `parts` in order, no parts give UTnfa, that matches empty string
+    pub fn concat_all(parts: impl IntoIterator<Item = UTnfa>) -> Self {
+        parts.into_iter().fold(Self::empty(), |a, b| a + b)
+    }
+
+    /// Unions all `parts`, no parts give UTnfa, that matches nothing
+    pub fn union_all(parts: impl IntoIterator<Item = UTnfa>) -> Self {
+        parts.into_iter().fold(Self::nothing(), |a, b| a | b)
+    }
+
     /// Concatenates `self` with `nfa`
     pub fn concat(&mut self, nfa: &UTnfa) {
         self.merge(nfa);
         self.eps_edges.push((self.end, nfa.begin, -1));
         self.end = nfa.end;
+        #[cfg(debug_assertions)]
+        self.validate();
     }
 
     /// Unions `self` with `nfa`, i.e. applies `|` operator
@@ -58,6 +183,8 @@ impl UTnfa {
         self.eps_edges.push((self.begin, nfa.begin, -1));
         self.append_node();
         self.eps_edges.push((nfa.end, self.end, -1));
+        #[cfg(debug_assertions)]
+        self.validate();
     }
 
     /// Applies kleene start to `self`, i.e. applies `*` operator
@@ -66,6 +193,37 @@ impl UTnfa {
         self.append_node();
         self.eps_edges.push((self.end, self.begin, -1));
         self.end = self.begin;
+        #[cfg(debug_assertions)]
+        self.validate();
+    }
+
+    /// Applies lazy kleene star to `self`, i.e. applies `*?` operator.
+    /// Unlike `kleene`, leaving the loop is preferred over entering its body,
+    /// so the matcher prefers paths, that repeat `self` as few times as possible
+    pub fn kleene_lazy(&mut self) {
+        let (begin, end) = (self.nodes, self.nodes + 1);
+        self.nodes += 2;
+        self.eps_edges.push((begin, end, -1));
+        self.eps_edges.push((begin, self.begin, -1));
+        self.eps_edges.push((self.end, begin, -1));
+        self.begin = begin;
+        self.end = end;
+        #[cfg(debug_assertions)]
+        self.validate();
+    }
+
+    /// Makes `self` match one or more times, i.e. applies `+` operator
+    pub fn plus(&mut self) {
+        let mut nfa = self.clone();
+        nfa.kleene();
+        self.concat(&nfa);
+    }
+
+    /// Makes `self` match one or more times lazily, i.e. applies `+?` operator, see `kleene_lazy`
+    pub fn plus_lazy(&mut self) {
+        let mut nfa = self.clone();
+        nfa.kleene_lazy();
+        self.concat(&nfa);
     }
 
     /// Makes `self` optional, i.e. applies `?` operator
@@ -73,6 +231,139 @@ impl UTnfa {
         self.union(&Self::empty())
     }
 
+    /// Makes `self` optional lazily, i.e. applies `??` operator,
+    /// so skipping `self` is preferred over matching it
+    pub fn optional_lazy(&mut self) {
+        let mut nfa = Self::empty();
+        nfa.union(self);
+        *self = nfa;
+    }
+
+    /// Concatenates `self` with itself `n` times, i.e. applies `{n}` operator.
+    /// Repeating zero times makes `self` match the empty string
+    pub fn repeat(&mut self, n: usize) {
+        if n == 0 {
+            *self = Self::empty();
+            return;
+        }
+        let nfa = self.clone();
+        for _ in 1..n {
+            self.concat(&nfa);
+        }
+    }
+
+    /// Returns index of the only final state, see `Automata::begin` for the initial one.
+    /// For `empty()` both are the same state
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Returns `true` if there is a cycle, consisting only of epsilon edges,
+    /// e.g. `kleene` applied twice creates one
+    pub fn has_eps_cycle(&self) -> bool {
+        let eps = SimpleAutomata {
+            begin: self.begin,
+            nodes: self.nodes,
+            finals: HashSet::new(),
+            edges: Vec::from_iter(self.eps_edges.iter().map(|(a, b, t)| (*a, *b, None, *t))),
+        };
+        self.eps_edges.iter().any(|e| e.0 == e.1) || eps.sccs().iter().any(|c| c.len() > 1)
+    }
+
+    /// Returns all states, reachable from `states` by epsilon edges (including `states` themselves).
+    /// Each state is visited once, so epsilon cycles are handled
+    pub fn eps_closure(&self, states: &BTreeSet<usize>) -> BTreeSet<usize> {
+        self.eps_closure_tags(states).0
+    }
+
+    /// Same as `eps_closure`, but also returns all tags, crossed on the way
+    pub fn eps_closure_tags(&self, states: &BTreeSet<usize>) -> (BTreeSet<usize>, BTreeSet<isize>) {
+        let mut eps_edges = vec![Vec::new(); self.nodes];
+        for (a, b, t) in self.eps_edges.iter() {
+            eps_edges[*a].push((*b, *t));
+        }
+        let mut closure = states.clone();
+        let mut tags = BTreeSet::new();
+        let mut stack = Vec::from_iter(states.iter().copied());
+        while let Some(n) = stack.pop() {
+            for (m, t) in eps_edges[n].iter() {
+                if *t != -1 {
+                    tags.insert(*t);
+                }
+                if closure.insert(*m) {
+                    stack.push(*m);
+                }
+            }
+        }
+        (closure, tags)
+    }
+
+    /// Returns number of tags, needed to store all tags of `self`, i.e. maximal tag plus one,
+    /// or 0 if `self` has no tags. Anchors are not counted
+    pub fn num_tags(&self) -> usize {
+        self.eps_edges
+            .iter()
+            .map(|e| e.2 + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as usize
+    }
+
+    /// Returns set of all distinct tags, used by `self`. Anchors are not included
+    pub fn tags(&self) -> BTreeSet<isize> {
+        BTreeSet::from_iter(self.eps_edges.iter().map(|e| e.2).filter(|t| *t >= 0))
+    }
+
+    /// Returns estimated heap usage of `self` in bytes, i.e. capacities of edge lists
+    /// multiplied by sizes of their elements. Nodes are not stored explicitly, so they take no memory
+    pub fn mem_bytes(&self) -> usize {
+        self.edges.capacity() * size_of::<(usize, usize, Charset)>()
+            + self.eps_edges.capacity() * size_of::<(usize, usize, isize)>()
+    }
+
+    /// Checks structural invariants of `self`: `begin`, `end` and all edge endpoints are valid nodes,
+    /// and all tags are either non-negative, `-1` or anchors. Panics if any of them is violated.
+    /// Called by `concat`, `union` and `kleene` in debug builds
+    pub fn validate(&self) {
+        assert!(self.begin < self.nodes, "begin is out of range");
+        assert!(self.end < self.nodes, "end is out of range");
+        for (a, b, _) in self.edges.iter() {
+            assert!(*a < self.nodes && *b < self.nodes, "edge is out of range");
+        }
+        for (a, b, t) in self.eps_edges.iter() {
+            assert!(*a < self.nodes && *b < self.nodes, "edge is out of range");
+            assert!(*t >= -1 || Anchor::from_tag(*t).is_some(), "invalid tag");
+        }
+    }
+
+    /// Renders `self` in graphviz DOT format.
+    /// Non-epsilon edges are labeled with their charsets, epsilon edges are labeled as `ε`,
+    /// as `ε/t=N` if they carry tag `N`, or as `ε/Start` etc. if they carry an anchor
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph {\n    rankdir=LR;\n");
+        for n in 0..self.nodes {
+            let shape = if n == self.end {
+                "doublecircle"
+            } else {
+                "circle"
+            };
+            s += &format!("    n{n} [label=\"{n}\", shape={shape}];\n");
+        }
+        s += &format!("    start [shape=point];\n    start -> n{};\n", self.begin);
+        for (a, b, c) in self.edges.iter() {
+            let label = c.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+            s += &format!("    n{a} -> n{b} [label=\"{label}\"];\n");
+        }
+        for (a, b, t) in self.eps_edges.iter() {
+            match (t, Anchor::from_tag(*t)) {
+                (-1, _) => s += &format!("    n{a} -> n{b} [label=\"ε\"];\n"),
+                (_, Some(anchor)) => s += &format!("    n{a} -> n{b} [label=\"ε/{anchor:?}\"];\n"),
+                _ => s += &format!("    n{a} -> n{b} [label=\"ε/t={t}\"];\n"),
+            }
+        }
+        s + "}\n"
+    }
+
     /// Increases all node indices by `n`
     fn shift(&mut self, n: usize) {
         self.begin += n;
@@ -85,8 +376,10 @@ impl UTnfa {
         }
     }
 
-    /// Merges `self` with `nfa` by shifting `self` and appending all `nfa` edges
+    /// Merges `self` with `nfa` by shifting `self` and appending all `nfa` edges.
+    /// Space is reserved for epsilon edges, that `concat` and `union` add after merging
     fn merge(&mut self, nfa: &UTnfa) {
+        self.reserve(nfa.edges.len(), nfa.eps_edges.len() + 4);
         self.shift(nfa.nodes);
         self.nodes += nfa.nodes;
         self.edges.extend(nfa.edges.iter());
@@ -108,6 +401,58 @@ impl UTnfa {
     }
 }
 
+impl Default for UTnfa {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+/// Creates UTnfa, that matches utf-8 representation of char
+impl From<char> for UTnfa {
+    fn from(c: char) -> Self {
+        let mut buf = [0; 4];
+        Self::from(&*c.encode_utf8(&mut buf))
+    }
+}
+
+/// Creates UTnfa, that matches bytes of string
+impl From<&str> for UTnfa {
+    fn from(s: &str) -> Self {
+        Self::concat_all(s.bytes().map(|c| Self::charset(Charset::from_char(c))))
+    }
+}
+
+/// Concatenates two UTnfa's, i.e. `a + b` is the same as `a.concat(&b)`.
+/// As `+` has higher precedence than `|`, `a + b | c` is `(a + b) | c`, just like in regexes
+impl Add for UTnfa {
+    type Output = UTnfa;
+
+    fn add(mut self, rhs: UTnfa) -> UTnfa {
+        self.concat(&rhs);
+        self
+    }
+}
+
+/// Unions two UTnfa's, i.e. `a | b` is the same as `a.union(&b)`
+impl BitOr for UTnfa {
+    type Output = UTnfa;
+
+    fn bitor(mut self, rhs: UTnfa) -> UTnfa {
+        self.union(&rhs);
+        self
+    }
+}
+
+/// Repeats UTnfa, i.e. `a * n` is the same as `a.repeat(n)`
+impl Mul<usize> for UTnfa {
+    type Output = UTnfa;
+
+    fn mul(mut self, rhs: usize) -> UTnfa {
+        self.repeat(rhs);
+        self
+    }
+}
+
 impl Automata for UTnfa {
     fn begin(&self) -> usize {
         self.begin
@@ -127,6 +472,13 @@ impl Automata for UTnfa {
             .flat_map(|(a, b, c)| c.iter().map(|c| (*a, *b, Some(c), -1)))
             .chain(self.eps_edges.iter().map(|(a, b, c)| (*a, *b, None, *c)))
     }
+
+    fn list_range_edges(&self) -> impl Iterator<Item = (usize, usize, Option<Charset>, isize)> {
+        self.edges
+            .iter()
+            .map(|(a, b, c)| (*a, *b, Some(*c), -1))
+            .chain(self.eps_edges.iter().map(|(a, b, c)| (*a, *b, None, *c)))
+    }
 }
 
 impl<T: Automata> PartialEq<T> for UTnfa {
@@ -139,7 +491,7 @@ impl<T: Automata> PartialEq<T> for UTnfa {
 mod utnfa_test {
     use super::*;
     use crate::automata::SimpleAutomata;
-    use std::collections::HashSet;
+    use crate::collections::HashSet;
 
     #[test]
     fn simple_test() {
@@ -148,9 +500,423 @@ mod utnfa_test {
             SimpleAutomata {
                 begin: 0,
                 nodes: 1,
-                finals: HashSet::new(),
+                finals: HashSet::from([0]),
                 edges: vec![]
             }
         );
     }
+
+    #[test]
+    fn operators_test() {
+        let a = UTnfa::charset(Charset::from_char(b'a'));
+        let b = UTnfa::charset(Charset::from_char(b'b'));
+        let c = UTnfa::charset(Charset::from_char(b'c'));
+
+        let mut x = a.clone();
+        x.union(&b);
+        assert_eq!(a.clone() | b.clone(), x);
+
+        let mut x = a.clone();
+        x.concat(&b);
+        assert_eq!(a.clone() + b.clone(), x);
+
+        x.union(&c);
+        #[allow(clippy::precedence)]
+        let y = a + b | c;
+        assert_eq!(y, x);
+        assert!(y.accepts(b"ab"));
+        assert!(y.accepts(b"c"));
+        assert!(!y.accepts(b"ac"));
+    }
+
+    #[test]
+    #[allow(clippy::erasing_op, clippy::identity_op)]
+    fn repeat_test() {
+        let a = UTnfa::charset(Charset::from_range((b'a', b'b')));
+
+        let mut x = a.clone();
+        x.concat(&a);
+        x.concat(&a);
+        let y = a.clone() * 3;
+        assert_eq!(y, x);
+        assert!(y.accepts(b"aba"));
+        assert!(!y.accepts(b"ab"));
+        assert!(!y.accepts(b"abab"));
+
+        assert_eq!(a.clone() * 1, a);
+        assert_eq!(a.clone() * 0, UTnfa::empty());
+        assert!((a * 0).accepts(b""));
+    }
+
+    #[test]
+    fn any_test() {
+        let a = UTnfa::any_byte();
+        assert!((0..=255).all(|c| a.accepts(&[c])));
+        assert!(!a.accepts(b""));
+        assert!(!a.accepts(b"ab"));
+
+        let a = UTnfa::any_char();
+        for s in ["a", "\0", "é", "€", "😀", "\u{10ffff}"] {
+            assert!(a.accepts(s.as_bytes()));
+        }
+        for s in [
+            &b""[..],
+            b"ab",
+            b"\xff",
+            b"\x80",
+            b"\xc3",
+            b"\xc0\x80",
+            b"\xe2\x82",
+        ] {
+            assert!(!a.accepts(s));
+        }
+    }
+
+    #[test]
+    fn concat_all_test() {
+        let lit =
+            |s: &str| UTnfa::concat_all(s.bytes().map(|c| UTnfa::charset(Charset::from_char(c))));
+        let a = UTnfa::concat_all([lit("ab"), lit("c"), lit("de")]);
+        assert!(a.accepts(b"abcde"));
+        assert!(!a.accepts(b"abcd"));
+        assert!(!a.accepts(b"ab"));
+
+        let a = UTnfa::union_all([lit("ab"), lit("c"), lit("de")]);
+        for s in [&b"ab"[..], b"c", b"de"] {
+            assert!(a.accepts(s));
+        }
+        for s in [&b""[..], b"a", b"abc", b"d"] {
+            assert!(!a.accepts(s));
+        }
+
+        let a = UTnfa::concat_all([]);
+        assert!(a.accepts(b""));
+        assert!(!a.accepts(b"a"));
+        assert!(UTnfa::union_all([]).is_empty_language());
+    }
+
+    #[test]
+    fn empty_charset_test() {
+        let a = UTnfa::charset(Charset::empty());
+        assert_eq!(a.nodes(), 2);
+        assert!(a.edges.is_empty() && a.eps_edges.is_empty());
+        assert_eq!(a.list_edges().count(), 0);
+        assert!(a.is_empty_language());
+        assert!(!a.accepts(b""));
+        assert!(!a.accepts(b"a"));
+
+        let a = UTnfa::charset(Charset::empty()) | UTnfa::charset(Charset::from_char(b'a'));
+        assert!(a.accepts(b"a"));
+        assert!(!a.accepts(b""));
+    }
+
+    #[test]
+    fn eps_closure_test() {
+        let c = |c| UTnfa::charset(Charset::from_char(c));
+        let a = c(b'a') | c(b'b');
+        let begin = BTreeSet::from([a.begin()]);
+        let closure = a.eps_closure(&begin);
+        for ch in [b'a', b'b'] {
+            let (from, _, _, _) = a.list_edges().find(|e| e.2 == Some(ch)).unwrap();
+            assert!(closure.contains(&from));
+        }
+        assert!(closure.contains(&a.begin()));
+        assert!(!closure.contains(&a.end));
+        assert_eq!(closure.len(), 3);
+
+        let a = UTnfa::tag(2) + UTnfa::tag(5) + c(b'a') + UTnfa::tag(7);
+        let (closure, tags) = a.eps_closure_tags(&BTreeSet::from([a.begin()]));
+        assert_eq!(tags, BTreeSet::from([2, 5]));
+        assert_eq!(closure, a.eps_closure(&BTreeSet::from([a.begin()])));
+        assert!(!closure.contains(&a.end));
+        assert_eq!(a.eps_closure(&BTreeSet::new()), BTreeSet::new());
+    }
+
+    #[test]
+    fn eps_cycle_test() {
+        let mut a = UTnfa::charset(Charset::from_char(b'a'));
+        assert!(!a.has_eps_cycle());
+        a.kleene();
+        assert!(!a.has_eps_cycle());
+        a.kleene();
+        assert!(a.has_eps_cycle());
+
+        // all states, except the one after `a` and the one after it, are in the closure
+        let closure = a.eps_closure(&BTreeSet::from([a.begin()]));
+        let (_, after, _, _) = a.list_edges().find(|e| e.2 == Some(b'a')).unwrap();
+        assert_eq!(closure.len(), a.nodes() - 2);
+        assert!(!closure.contains(&after));
+        assert!(closure.contains(&a.begin()) && closure.contains(&a.end));
+        assert!(a.accepts(b"") && a.accepts(b"aaa") && !a.accepts(b"b"));
+
+        assert!(!UTnfa::tag(0).has_eps_cycle());
+        let mut a = UTnfa::empty();
+        a.kleene();
+        assert!(a.has_eps_cycle());
+    }
+
+    #[test]
+    fn from_automata_test() {
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from_iter([2]),
+            edges: vec![
+                (0, 1, Some(b'a'), -1),
+                (0, 1, Some(b'b'), -1),
+                (1, 1, Some(b'c'), -1),
+                (1, 2, None, 3),
+            ],
+        };
+        let nfa = UTnfa::from_automata(&a);
+        nfa.validate();
+        assert_eq!(nfa, a);
+        assert_eq!(nfa.tags(), BTreeSet::from([3]));
+
+        let a = SimpleAutomata {
+            begin: 0,
+            nodes: 3,
+            finals: HashSet::from_iter([1, 2]),
+            edges: vec![(0, 1, Some(b'a'), 5), (0, 2, Some(b'b'), -1)],
+        };
+        let mut nfa = UTnfa::from_automata(&a);
+        nfa.validate();
+        assert_eq!(nfa.nodes(), 5);
+        assert_eq!(nfa.tags(), BTreeSet::from([5]));
+        nfa.concat(&UTnfa::charset(Charset::from_char(b'x')));
+        for (s, ok) in [
+            ("ax", true),
+            ("bx", true),
+            ("a", false),
+            ("x", false),
+            ("abx", false),
+        ] {
+            assert_eq!(nfa.accepts(s.as_bytes()), ok);
+        }
+        assert!(UTnfa::from_automata(&UTnfa::nothing()).is_empty_language());
+    }
+
+    #[test]
+    fn mem_bytes_test() {
+        assert_eq!(UTnfa::empty().mem_bytes(), 0);
+        let edge = UTnfa::with_capacity(1, 0).mem_bytes();
+        let eps_edge = UTnfa::with_capacity(0, 1).mem_bytes();
+        assert!(edge > eps_edge && eps_edge > 0);
+        for n in [10, 100, 1000] {
+            assert_eq!(
+                UTnfa::with_capacity(n, 2 * n).mem_bytes(),
+                n * (edge + 2 * eps_edge)
+            );
+        }
+
+        let c = |c| UTnfa::charset(Charset::from_char(c));
+        let small = UTnfa::concat_all((0..10).map(|_| c(b'a')));
+        let large = UTnfa::concat_all((0..1000).map(|_| c(b'a')));
+        assert!(large.mem_bytes() >= 1000 * edge);
+        assert!(large.mem_bytes() > 50 * small.mem_bytes());
+    }
+
+    #[test]
+    fn validate_test() {
+        let c = |c| UTnfa::charset(Charset::from_char(c));
+        let mut a = c(b'a');
+        for i in 0..5 {
+            let mut b = a.clone() | (c(b'b') + UTnfa::tag(i));
+            b.kleene();
+            b.optional();
+            b.plus();
+            a = (b + a.clone()) * 2;
+            a.validate();
+        }
+        assert_eq!(a.shortest_match(), Some(vec![b'a'; 32]));
+        UTnfa::empty().validate();
+        UTnfa::nothing().validate();
+    }
+
+    #[test]
+    #[should_panic]
+    fn validate_invalid_test() {
+        let mut a = UTnfa::charset(Charset::from_char(b'a'));
+        a.edges.push((0, 2, Charset::from_char(b'b')));
+        a.validate();
+    }
+
+    #[test]
+    fn with_capacity_test() {
+        let mut a = UTnfa::with_capacity(1000, 4000);
+        assert_eq!(a, UTnfa::empty());
+        let capacity = (a.edges.capacity(), a.eps_edges.capacity());
+        assert!(capacity.0 >= 1000 && capacity.1 >= 4000);
+        for c in 0..1000 {
+            a.union(&UTnfa::charset(Charset::from_char((c % 256) as u8)));
+            assert_eq!((a.edges.capacity(), a.eps_edges.capacity()), capacity);
+        }
+        assert!(a.accepts(b"") && a.accepts(b"x"));
+
+        let mut a = UTnfa::empty();
+        a.reserve(10, 20);
+        assert!(a.edges.capacity() >= 10 && a.eps_edges.capacity() >= 20);
+    }
+
+    #[test]
+    fn from_str_test() {
+        let a = UTnfa::from('é');
+        assert!(a.accepts(&[0xc3, 0xa9]));
+        for s in [&b""[..], b"\xc3", b"\xa9", b"e", b"\xc3\xa9\xc3\xa9"] {
+            assert!(!a.accepts(s));
+        }
+        assert_eq!(UTnfa::from("é"), a);
+
+        let a: UTnfa = "keyword".into();
+        assert!(a.accepts(b"keyword"));
+        assert!(!a.accepts(b"keywords") && !a.accepts(b"key"));
+        assert!(UTnfa::from("").accepts(b""));
+        assert!(UTnfa::from('a').accepts(b"a"));
+    }
+
+    #[test]
+    fn end_test() {
+        let mut ab = UTnfa::charset(Charset::from_char(b'a'));
+        ab.concat(&UTnfa::tag(3));
+        ab.union(&UTnfa::charset(Charset::from_char(b'b')));
+        ab.kleene();
+        for a in [UTnfa::empty(), UTnfa::nothing(), UTnfa::any_char(), ab] {
+            assert_eq!(a.final_states(), vec![a.end()]);
+            assert!((0..a.nodes()).all(|n| a.is_final(n) == (n == a.end())));
+        }
+        assert_eq!(UTnfa::empty().end(), UTnfa::empty().begin());
+    }
+
+    #[test]
+    fn default_test() {
+        assert_eq!(UTnfa::default(), UTnfa::empty());
+        let mut a = UTnfa::charset(Charset::from_char(b'a'));
+        assert_eq!(
+            core::mem::take(&mut a),
+            UTnfa::charset(Charset::from_char(b'a'))
+        );
+        assert_eq!(a, UTnfa::empty());
+    }
+
+    #[test]
+    fn tags_test() {
+        let mut a = UTnfa::tag(0);
+        a.concat(&UTnfa::charset(Charset::from_char(b'a')));
+        a.concat(&UTnfa::tag(3));
+        a.kleene();
+        assert_eq!(a.num_tags(), 4);
+        assert_eq!(a.tags(), BTreeSet::from([0, 3]));
+
+        let a = UTnfa::charset(Charset::from_char(b'a')) | UTnfa::empty();
+        assert_eq!(a.num_tags(), 0);
+        assert_eq!(a.tags(), BTreeSet::new());
+
+        let a = UTnfa::anchor(Anchor::Start);
+        a.validate();
+        assert_eq!(a.num_tags(), 0);
+        assert_eq!(a.tags(), BTreeSet::new());
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_tag_test() {
+        UTnfa::tag(-1);
+    }
+
+    #[test]
+    fn to_dot_test() {
+        let dot = UTnfa::tag(2).to_dot();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("n0 -> n1 [label=\"ε/t=2\"];"));
+        assert!(dot.contains("n1 [label=\"1\", shape=doublecircle];"));
+        assert!(dot.contains("start -> n0;"));
+
+        let mut a = UTnfa::charset(Charset::from_range((b'a', b'c')));
+        a.concat(&UTnfa::charset(Charset::from_char(b'"')));
+        let dot = a.to_dot();
+        assert!(dot.contains("[label=\"abc\"];"));
+        assert!(dot.contains("[label=\"\\\"\"];"));
+        assert!(dot.contains("[label=\"ε\"];"));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_test() {
+        let mut a = UTnfa::charset(Charset::from_range((b'a', b'z')));
+        a.kleene();
+        a.concat(&UTnfa::tag(1));
+        let json = serde_json::to_string(&a).unwrap();
+        let b: UTnfa = serde_json::from_str(&json).unwrap();
+        assert_eq!(a, b);
+        assert!(b.accepts(b"abc"));
+    }
+
+    #[test]
+    fn optional_test() {
+        let a = || UTnfa::charset(Charset::from_char(b'a'));
+        let b = || UTnfa::charset(Charset::from_char(b'b'));
+        let words = |nfa: &UTnfa| nfa.matches_up_to(3);
+
+        let mut opt = a();
+        opt.optional();
+        assert_eq!(words(&opt), vec![b"".to_vec(), b"a".to_vec()]);
+        assert!(!opt.accepts(b"aa"));
+
+        // optional after kleene must not make the loop body skippable in a wrong way
+        let mut star_opt = a();
+        star_opt.kleene();
+        star_opt.optional();
+        let mut star = a();
+        star.kleene();
+        assert_eq!(words(&star_opt), words(&star));
+
+        let mut opt_star = a();
+        opt_star.optional();
+        opt_star.kleene();
+        assert_eq!(words(&opt_star), words(&star));
+
+        // `(a*)?b` and `a?b*`: the empty branch doesn't leak into neighbours
+        let mut x = star_opt.clone();
+        x.concat(&b());
+        assert_eq!(
+            words(&x),
+            vec![b"aab".to_vec(), b"ab".to_vec(), b"b".to_vec()]
+        );
+        let mut b_star = b();
+        b_star.kleene();
+        let mut y = opt.clone();
+        y.concat(&b_star);
+        assert!(y.accepts(b"") && y.accepts(b"abb") && y.accepts(b"bb"));
+        assert!(!y.accepts(b"aab") && !y.accepts(b"ba"));
+
+        let mut lazy = a();
+        lazy.kleene_lazy();
+        lazy.optional();
+        assert_eq!(words(&lazy), words(&star));
+        let mut lazy = a();
+        lazy.optional_lazy();
+        assert_eq!(words(&lazy), words(&opt));
+        for (pattern, nfa) in [
+            ("a*?", &star),
+            ("a??", &opt),
+            ("(a*)?", &star),
+            ("(a?)*?", &star),
+        ] {
+            assert_eq!(
+                words(&crate::parse(pattern).unwrap()),
+                words(nfa),
+                "{pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn plus_test() {
+        let mut a = UTnfa::charset(Charset::from_char(b'a'));
+        a.plus();
+        assert!(!a.accepts(b""));
+        assert!(a.accepts(b"a"));
+        assert!(a.accepts(b"aaaa"));
+        assert!(!a.accepts(b"aab"));
+    }
 }
@@ -0,0 +1,21 @@
+//! Checks the whole pipeline from a pattern string to a running matcher
+
+use rcclex::compile;
+
+#[test]
+fn compile_email_test() {
+    let regex = compile("([a-z]+)@([a-z]+)").unwrap();
+    assert!(regex.matches(b"user@example"));
+    assert!(!regex.matches(b"user@"));
+    assert!(!regex.matches(b"user@example.com"));
+    assert!(!regex.matches(b"User@example"));
+    assert_eq!(regex.longest_prefix(b"user@example.com"), Some(12));
+
+    let c = regex.captures(b"user@example").unwrap();
+    assert_eq!(c.get(0), Some(&b"user@example"[..]));
+    assert_eq!(c.get(1), Some(&b"user"[..]));
+    assert_eq!(c.get(2), Some(&b"example"[..]));
+    assert!(regex.captures(b"user@example.com").is_none());
+
+    assert!(compile("[a-z").is_err());
+}
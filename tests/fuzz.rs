@@ -0,0 +1,98 @@
+//! Fuzz tests of the whole pipeline: `parse`, `Tnfa`, determinization, `compile` and matchers.
+//! Each pattern must either be rejected by the parser, or produce valid automata, that can be
+//! determinized and run without panics.
+//! Seed corpus is always checked, random patterns require `proptest` feature:
+//! `cargo test --features proptest --test fuzz`. Set `PROPTEST_CASES` to run more cases
+
+use rcclex::{Automata, DeterminizeOptions, Matcher, Tdfa, Tnfa, compile, parse, parse_bytes};
+
+/// Patterns, that used to be tricky for the parser
+const SEEDS: &[&str] = &[
+    "",
+    "[]",
+    "[]]",
+    "[^]",
+    "a{,}",
+    "a{}",
+    "a{1,0}",
+    "a{2}{3}",
+    "(((",
+    ")))",
+    "()",
+    "(|)",
+    "\\",
+    "\\\\",
+    "\\x",
+    "\\xzz",
+    "[a-]",
+    "[-a]",
+    "[z-a]",
+    "a**",
+    "a*?*",
+    "*",
+    "a|*",
+    "^$",
+    "\\b*",
+    "(?P<a>x)(?P<a>y)",
+    "(?P<>x)",
+    "(?i)",
+    "(?i:é)",
+    "(?",
+    "é{3}",
+    "[^é]",
+    ".*.",
+    "a{0,100000}",
+    "a{100000}{100000}",
+    "a{99999999999999999999999}",
+    "a{1000}{1000}{1000}",
+    "((a{1000}){1000}){1000}",
+];
+
+/// Limit of determinized states, so that random repetitions don't blow up
+const MAX_STATES: usize = 10_000;
+
+/// Runs the whole pipeline on `pattern`, panics if any stage misbehaves
+fn check(pattern: &str) {
+    for nfa in [parse(pattern), parse_bytes(pattern)] {
+        let Ok(nfa) = nfa else {
+            continue;
+        };
+        nfa.validate();
+        let options = DeterminizeOptions {
+            max_states: Some(MAX_STATES),
+        };
+        let Ok(dfa) = Tdfa::determinize_with(&Tnfa::from(&nfa), &options) else {
+            continue;
+        };
+        assert!(dfa.is_deterministic());
+        for input in [&b""[..], b"abc", b"aaa\xc3\xa9", b"(x)"] {
+            let accepted = nfa.accepts(input);
+            assert_eq!(dfa.accepts(input), accepted, "{pattern:?} on {input:?}");
+            if let Some((start, end)) = Matcher::new(&nfa).find(input).and_then(|m| m.groups[0]) {
+                assert!(start <= end && end <= input.len());
+            }
+        }
+    }
+    if let Ok(regex) = compile(pattern) {
+        let input = b"abcab";
+        assert_eq!(regex.captures(input).is_some(), regex.matches(input));
+    }
+}
+
+#[test]
+fn seed_corpus_test() {
+    SEEDS.iter().for_each(|p| check(p));
+}
+
+#[cfg(feature = "proptest")]
+mod random {
+    use proptest::prelude::*;
+    use rcclex::strategies::pattern;
+
+    proptest! {
+        #[test]
+        fn random_patterns(p in pattern(16)) {
+            super::check(&p);
+        }
+    }
+}
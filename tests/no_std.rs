@@ -0,0 +1,21 @@
+//! Checks, that automata can be built and run without `std`.
+//! The crate itself is `no_std` only with `no_std` feature, so run as `cargo test --features no_std`
+#![no_std]
+
+extern crate alloc;
+
+use rcclex::{Automata, Charset, Tdfa, Tnfa, UTnfa, parse};
+
+#[test]
+fn no_std_test() {
+    let mut a = UTnfa::charset(Charset::from_range((b'a', b'z')));
+    a.kleene();
+    a.concat(&UTnfa::charset(Charset::from_char(b'!')));
+    assert!(a.accepts(b"hello!"));
+    assert!(!a.accepts(b"hello"));
+
+    let dfa = Tdfa::determinize(&Tnfa::from(&parse("(ab|c)+").unwrap()));
+    assert!(dfa.is_deterministic());
+    assert!(dfa.accepts(b"abcab"));
+    assert!(!dfa.accepts(b"abb"));
+}